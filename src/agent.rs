@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use crate::board::{Board, Move, Pile};
+use crate::game::{Card, CardFace, Suit};
+
+/// What a player can see of the game. The board underneath is kept private so agents can't peek at face down cards
+#[derive(Debug, Clone)]
+pub struct Observation {
+    /// Each tableau pile from the bottom up, `None` for face down cards
+    pub tableau: Vec<Vec<Option<Card>>>,
+    /// Cards left in the stock
+    pub stock: usize,
+    pub waste_top: Option<Card>,
+    pub reserve_top: Option<Card>,
+    /// Cards left in the reserve
+    pub reserve: usize,
+    /// Suit and top card of each foundation
+    pub foundations: Vec<(Suit, Option<Card>)>,
+    /// Passes through the stock left, including the current one. `None` if there is no limit
+    pub passes_left: Option<usize>,
+    /// Every legal move
+    pub moves: Vec<Move>,
+    board: Board,
+}
+
+impl Observation {
+    pub fn new(board: &Board) -> Self {
+        Self {
+            tableau: board.tableau.iter()
+                .map(|tableau| tableau.cards.iter().map(|(card, face)| (*face == CardFace::Up).then_some(*card)).collect())
+                .collect(),
+            stock: board.stock.len(),
+            waste_top: board.top(Pile::Waste),
+            reserve_top: board.top(Pile::Reserve),
+            reserve: board.reserve.len(),
+            foundations: board.foundations.iter().map(|foundation| (foundation.suit, foundation.cards.last().copied())).collect(),
+            passes_left: board.passes.map(|passes| passes.saturating_sub(board.recycles)),
+            moves: board.moves(),
+            board: board.clone(),
+        }
+    }
+
+    /// What the game would look like after a move, unless the move turns over cards that can't be seen yet
+    pub fn after(&self, mv: Move) -> Option<Observation> {
+        let mut board = self.board.clone();
+        board.apply(mv);
+        if hidden(&board) < hidden(&self.board) {
+            None
+        } else {
+            Some(Observation::new(&board))
+        }
+    }
+
+    /// Return true if the card can go straight onto one of the foundations
+    pub fn fits_foundation(&self, card: Card) -> bool {
+        (0..self.foundations.len()).any(|i| self.board.accepts(Pile::Foundation(i), card, 1))
+    }
+
+    pub fn is_won(&self) -> bool {
+        self.board.is_won()
+    }
+}
+
+/// Number of cards the player hasn't seen
+fn hidden(board: &Board) -> usize {
+    let face_down = |cards: &[(Card, CardFace)]| cards.iter().filter(|(_, face)| *face == CardFace::Down).count();
+    board.stock.len()
+        + face_down(&board.reserve)
+        + board.tableau.iter().map(|tableau| face_down(&tableau.cards)).sum::<usize>()
+}
+
+/// Something that can play solitaire
+pub trait SolitaireAgent {
+    /// Pick the next move, or `None` to give up
+    fn choose(&mut self, observation: &Observation) -> Option<Move>;
+}
+
+/// Plays any legal move at random
+pub struct RandomAgent {
+    rng: StdRng,
+}
+
+impl RandomAgent {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl SolitaireAgent for RandomAgent {
+    fn choose(&mut self, observation: &Observation) -> Option<Move> {
+        observation.moves.choose(&mut self.rng).copied()
+    }
+}
+
+/// How good a move looks on its own, `None` for moves that don't get anywhere
+pub fn score(observation: &Observation, mv: Move) -> Option<i32> {
+    match mv {
+        // Lowest cards first since they free up the most
+        Move::Cards {to: Pile::Foundation(_), from, count: _} => {
+            let card = match from {
+                Pile::Waste => observation.waste_top,
+                Pile::Reserve => observation.reserve_top,
+                Pile::Tableau(i) => observation.tableau[i].last().copied().flatten(),
+                Pile::Foundation(_) => None,
+            }?;
+            Some(100 + 13 - card.kind.column() as i32)
+        },
+        Move::Cards {from: Pile::Tableau(from), to: Pile::Tableau(to), count} => {
+            let pile = &observation.tableau[from];
+            match pile.len().checked_sub(count + 1).map(|i| pile[i]) {
+                // Prefer digging into the piles with the most cards left to turn over
+                Some(None) => Some(60 + pile.iter().filter(|card| card.is_none()).count() as i32),
+                None if observation.tableau[to].is_empty() => None,
+                None => Some(20),
+                Some(Some(card)) if observation.fits_foundation(card) => Some(40),
+                Some(Some(_)) => None,
+            }
+        },
+        Move::Cards {from: Pile::Tableau(_), ..} => None,
+        Move::Cards {from: Pile::Reserve, ..} => Some(35),
+        Move::Cards {from: Pile::Waste, ..} => Some(30),
+        Move::Cards {from: Pile::Foundation(_), ..} => None,
+        Move::Draw => Some(5),
+        Move::Recycle => Some(1),
+    }
+}
+
+/// Always plays the move that looks best right now
+#[derive(Default)]
+pub struct GreedyAgent;
+
+impl SolitaireAgent for GreedyAgent {
+    fn choose(&mut self, observation: &Observation) -> Option<Move> {
+        observation.moves.iter()
+            .filter_map(|mv| score(observation, *mv).map(|score| (score, *mv)))
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, mv)| mv)
+    }
+}
+
+/// Plays the move leading to the best looking sequence of moves, searching as far as can be seen
+pub struct LookaheadAgent {
+    pub depth: usize,
+}
+
+impl Default for LookaheadAgent {
+    fn default() -> Self {
+        Self {
+            depth: 3,
+        }
+    }
+}
+
+impl LookaheadAgent {
+    fn value(&self, observation: &Observation, depth: usize) -> i32 {
+        observation.moves.iter()
+            .filter_map(|mv| score(observation, *mv).map(|score| score + self.follow_up(observation, *mv, depth)))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Best score after the move, discounted since the position may change before it is reached
+    fn follow_up(&self, observation: &Observation, mv: Move, depth: usize) -> i32 {
+        if depth == 0 {
+            return 0
+        }
+        match observation.after(mv) {
+            Some(next) if next.is_won() => 1000,
+            Some(next) => self.value(&next, depth - 1) / 2,
+            None => 0,
+        }
+    }
+}
+
+impl SolitaireAgent for LookaheadAgent {
+    fn choose(&mut self, observation: &Observation) -> Option<Move> {
+        let depth = self.depth.saturating_sub(1);
+        observation.moves.iter()
+            .filter_map(|mv| score(observation, *mv).map(|score| (score + self.follow_up(observation, *mv, depth), *mv)))
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, mv)| mv)
+    }
+}
+
+/// Reference agent by name, for picking one from the command line
+pub fn agent(name: &str, seed: u64) -> Option<Box<dyn SolitaireAgent>> {
+    match name {
+        "random" => Some(Box::new(RandomAgent::new(seed))),
+        "greedy" => Some(Box::new(GreedyAgent)),
+        "lookahead" => Some(Box::new(LookaheadAgent::default())),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GameResult {
+    pub won: bool,
+    pub moves: Vec<Move>,
+}
+
+/// Let an agent play a game to the end. The game is lost if the agent gives up, tries an illegal move,
+/// runs past `max_moves` or comes back to the same position a third time
+pub fn play(agent: &mut dyn SolitaireAgent, mut board: Board, max_moves: usize) -> GameResult {
+    let mut moves = Vec::new();
+    let mut seen: HashMap<u64, usize> = HashMap::new();
+    loop {
+        if board.is_won() {
+            return GameResult {won: true, moves}
+        }
+        let visits = seen.entry(board.key()).or_insert(0);
+        *visits += 1;
+        if moves.len() >= max_moves || *visits > 2 {
+            return GameResult {won: false, moves}
+        }
+        match agent.choose(&Observation::new(&board)) {
+            Some(mv) if board.moves().contains(&mv) => {
+                board.apply(mv);
+                moves.push(mv);
+            },
+            _ => return GameResult {won: false, moves},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::fixtures::*;
+    use crate::game::new_deck;
+
+    /// Play the moves over again, checking each one was legal when it was made
+    fn replay(mut board: Board, moves: &[Move]) -> Board {
+        for mv in moves {
+            assert!(board.moves().contains(mv), "{:?} wasn't legal", mv);
+            board.apply(*mv);
+        }
+        board
+    }
+
+    #[test]
+    fn face_down_cards_are_hidden() {
+        let board = klondike(5);
+        let observation = Observation::new(&board);
+        for (seen, tableau) in observation.tableau.iter().zip(&board.tableau) {
+            assert_eq!(seen.len(), tableau.cards.len());
+            for (seen, (card, face)) in seen.iter().zip(&tableau.cards) {
+                match face {
+                    CardFace::Down => assert_eq!(*seen, None),
+                    CardFace::Up => assert_eq!(*seen, Some(*card)),
+                }
+            }
+        }
+        assert_eq!(observation.tableau[6].iter().filter(|card| card.is_none()).count(), 6);
+        assert_eq!(observation.stock, 24);
+        assert_eq!(observation.waste_top, None);
+        assert_eq!(observation.moves, board.moves());
+        // Neither turning over a tableau card nor drawing from the stock can be looked ahead through
+        let uncover = board.moves().into_iter().find(|mv| matches!(mv, Move::Cards {from: Pile::Tableau(1..), ..})).unwrap();
+        assert!(observation.after(uncover).is_none());
+        assert!(observation.after(Move::Draw).is_none());
+    }
+
+    #[test]
+    fn random_agent_plays_legal_moves() {
+        let board = klondike(5);
+        let result = play(&mut RandomAgent::new(3), board.clone(), 500);
+        assert!(!result.moves.is_empty() && result.moves.len() <= 500);
+        let end = replay(board.clone(), &result.moves);
+        assert_eq!(end.is_won(), result.won);
+        // The same seed plays the same game
+        assert_eq!(play(&mut RandomAgent::new(3), board, 500).moves, result.moves);
+    }
+
+    #[test]
+    fn greedy_agent_wins_an_open_game() {
+        // Ace to jack of every suit home, with the queens on top of face down kings
+        let mut board = built_up(10);
+        let mut deck = new_deck();
+        deck.sort_by_key(|card| card.kind.column());
+        let kings = deck.iter().filter(|card| card.kind.column() == 12);
+        let queens = deck.iter().filter(|card| card.kind.column() == 11).rev();
+        for ((tableau, king), queen) in board.tableau.iter_mut().zip(kings).zip(queens) {
+            tableau.cards = vec![(*king, CardFace::Down), (*queen, CardFace::Up)];
+        }
+        let result = play(&mut GreedyAgent, board.clone(), 100);
+        assert!(result.won);
+        assert_eq!(result.moves.len(), 8);
+        assert!(replay(board, &result.moves).is_won());
+    }
+}
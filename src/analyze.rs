@@ -0,0 +1,124 @@
+//! Results written out by `solitaire-analyze`, one row per deal, and checking game records
+
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+use crate::agent::{play, SolitaireAgent};
+use crate::board::Board;
+use crate::notation::GameRecord;
+use crate::solver::{solve, Outcome};
+use crate::variant::Variant;
+
+pub const CSV_HEADER: &str = "seed,variant,draw,result,moves,nodes,millis";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Row {
+    pub seed: u64,
+    pub variant: String,
+    pub draw: usize,
+    /// solved, unsolvable, exhausted or unknown for the solver, won or lost for an agent
+    pub result: &'static str,
+    /// Length of the solution or the game played, draws included
+    pub moves: Option<usize>,
+    /// Positions searched by the solver
+    pub nodes: Option<usize>,
+    pub millis: u128,
+}
+
+impl Row {
+    /// Search the deal for a win, giving up once `budget` has passed
+    pub fn solve(seed: u64, variant: &Variant, board: &Board, budget: Duration) -> Self {
+        let report = solve(board, budget);
+        let (result, moves) = match report.outcome {
+            Outcome::Solved(moves) => ("solved", Some(moves.len())),
+            Outcome::Unsolvable => ("unsolvable", None),
+            Outcome::Exhausted => ("exhausted", None),
+            Outcome::Unknown => ("unknown", None),
+        };
+        Self {
+            seed,
+            variant: variant.name.clone(),
+            draw: board.draw,
+            result,
+            moves,
+            nodes: Some(report.nodes),
+            millis: report.elapsed.as_millis(),
+        }
+    }
+
+    /// Let an agent play the deal, losing if it runs past `max_moves`
+    pub fn play(seed: u64, variant: &Variant, board: &Board, agent: &mut dyn SolitaireAgent, max_moves: usize) -> Self {
+        let start = Instant::now();
+        let game = play(agent, board.clone(), max_moves);
+        Self {
+            seed,
+            variant: variant.name.clone(),
+            draw: board.draw,
+            result: if game.won {"won"} else {"lost"},
+            moves: Some(game.moves.len()),
+            nodes: None,
+            millis: start.elapsed().as_millis(),
+        }
+    }
+
+    /// One line of CSV under `CSV_HEADER`
+    pub fn csv(&self) -> String {
+        let optional = |n: Option<usize>| n.map(|n| n.to_string()).unwrap_or_default();
+        format!(
+            "{},{},{},{},{},{},{}",
+            self.seed, self.variant, self.draw, self.result, optional(self.moves), optional(self.nodes), self.millis
+        )
+    }
+}
+
+/// Every row as a pretty printed JSON array
+pub fn json(rows: &[Row]) -> String {
+    serde_json::to_string_pretty(rows).unwrap()
+}
+
+/// Replay a game record, describing how it went or the first thing wrong with it
+pub fn check(text: &str, variants: &[Variant]) -> Result<String, String> {
+    let record = GameRecord::parse(text)?;
+    record.replay(variants)?;
+    Ok(format!("{} moves, {}", record.moves.len(), record.result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::GreedyAgent;
+    use crate::board::fixtures::{klondike, variant};
+    use crate::variant::read_variants;
+
+    #[test]
+    fn rows() {
+        let klondike_variant = variant("Klondike");
+        let mut solved = Row::solve(42, &klondike_variant, &klondike(42), Duration::from_secs(60));
+        let mut played = Row::play(42, &klondike_variant, &klondike(42), &mut GreedyAgent, 1000);
+        solved.millis = 12;
+        played.millis = 3;
+        assert_eq!(solved.csv(), "42,Klondike,1,solved,137,101,12");
+        assert_eq!(played.csv(), "42,Klondike,1,lost,71,,3");
+        assert_eq!(json(&[played]), r#"[
+  {
+    "seed": 42,
+    "variant": "Klondike",
+    "draw": 1,
+    "result": "lost",
+    "moves": 71,
+    "nodes": null,
+    "millis": 3
+  }
+]"#);
+    }
+
+    #[test]
+    fn check_records() {
+        let variants = read_variants();
+        let record = "[Variant \"Klondike\"]\n[Seed \"42\"]\n[Draw \"1\"]\n\nt7-t5 d\n";
+        assert_eq!(check(record, &variants).unwrap(), "2 moves, unfinished");
+        assert_eq!(check(&record.replace("t7-t5", "t1-t2"), &variants).unwrap_err(), "move 1: t1-t2 is not a legal move");
+        assert_eq!(check(&record.replace("Klondike", "Spider"), &variants).unwrap_err(), "no variant named \"Spider\"");
+        assert_eq!(check("t7-t5 d", &variants).unwrap_err(), "missing Variant tag");
+    }
+}
@@ -0,0 +1,187 @@
+//! Deals a range of seeds without opening a window and reports how the solver, or one of the reference agents, did on each of them
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::time::Duration;
+
+use solitare::agent;
+use solitare::analyze::{self, Row, CSV_HEADER};
+use solitare::board::{Board, shuffled_deck};
+use solitare::game::{DrawMode, StockPasses};
+use solitare::variant::read_variants;
+
+const USAGE: &str = "Usage: solitaire-analyze [options]
+
+Options:
+  --variant <name>     Variant to deal (default Klondike)
+  --draw <1|3>         Cards flipped from the stock each draw (default 1)
+  --vegas              Limit passes through the stock as in Vegas rules
+  --open               Deal every card face up
+  --seeds <from>..<to> Seeds to deal, not including <to> (default 0..100)
+  --budget <ms>        Time the solver gets for each deal (default 2000)
+  --agent <name>       Play each deal with an agent instead of solving it: random, greedy or lookahead
+  --max-moves <n>      Moves an agent gets before the game counts as lost (default 1000)
+  --format <csv|json>  Output format (default csv)
+  --check <file>       Replay a game record and check every move in it is legal";
+
+#[derive(Debug, PartialEq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+struct Options {
+    variant: String,
+    draw_mode: DrawMode,
+    stock_passes: StockPasses,
+    open: bool,
+    seeds: (u64, u64),
+    budget: Duration,
+    agent: Option<String>,
+    max_moves: usize,
+    format: Format,
+    check: Option<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        variant: "Klondike".to_string(),
+        draw_mode: DrawMode::Draw1,
+        stock_passes: StockPasses::Unlimited,
+        open: false,
+        seeds: (0, 100),
+        budget: Duration::from_millis(2000),
+        agent: None,
+        max_moves: 1000,
+        format: Format::Csv,
+        check: None,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--variant" => options.variant = value()?,
+            "--draw" => options.draw_mode = match value()?.as_str() {
+                "1" => DrawMode::Draw1,
+                "3" => DrawMode::Draw3,
+                other => return Err(format!("can't draw {} cards, only 1 or 3", other)),
+            },
+            "--vegas" => options.stock_passes = StockPasses::Vegas,
+            "--open" => options.open = true,
+            "--seeds" => {
+                let seeds = value()?;
+                let (from, to) = seeds.split_once("..").ok_or_else(|| format!("seeds {:?} should look like 0..100", seeds))?;
+                let parse = |n: &str| n.parse::<u64>().map_err(|e| format!("bad seed {:?}: {}", n, e));
+                options.seeds = (parse(from)?, parse(to)?);
+            },
+            "--budget" => {
+                let budget = value()?;
+                options.budget = Duration::from_millis(budget.parse().map_err(|e| format!("bad budget {:?}: {}", budget, e))?);
+            },
+            "--agent" => {
+                let name = value()?;
+                if agent::agent(&name, 0).is_none() {
+                    return Err(format!("unknown agent {:?}", name))
+                }
+                options.agent = Some(name);
+            },
+            "--max-moves" => {
+                let max_moves = value()?;
+                options.max_moves = max_moves.parse().map_err(|e| format!("bad move limit {:?}: {}", max_moves, e))?;
+            },
+            "--format" => options.format = match value()?.as_str() {
+                "csv" => Format::Csv,
+                "json" => Format::Json,
+                other => return Err(format!("unknown format {:?}", other)),
+            },
+            "--check" => options.check = Some(value()?),
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            other => return Err(format!("unknown option {:?}", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+    if let Some(path) = &options.check {
+        check(path);
+        return
+    }
+    let variant = read_variants()
+        .into_iter()
+        .find(|variant| variant.name.eq_ignore_ascii_case(&options.variant))
+        .unwrap_or_else(|| {
+            eprintln!("No variant named {:?} in assets/variants", options.variant);
+            process::exit(1);
+        });
+    let draw = variant.draw_num(&options.draw_mode);
+    let passes = variant.pass_limit(&options.draw_mode, &options.stock_passes);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if options.format == Format::Csv {
+        writeln!(out, "{}", CSV_HEADER).unwrap();
+    }
+    let mut rows = Vec::new();
+    let (mut solved, mut unsolvable, mut exhausted) = (0, 0, 0);
+    for seed in options.seeds.0..options.seeds.1 {
+        let board = Board::deal(&variant, draw, passes, options.open, shuffled_deck(variant.decks, seed));
+        let row = match &options.agent {
+            Some(name) => Row::play(seed, &variant, &board, agent::agent(name, seed).unwrap().as_mut(), options.max_moves),
+            None => Row::solve(seed, &variant, &board, options.budget),
+        };
+        match row.result {
+            "solved" | "won" => solved += 1,
+            "unsolvable" => unsolvable += 1,
+            "exhausted" => exhausted += 1,
+            _ => (),
+        }
+        match options.format {
+            // Written as they finish so long runs can be watched
+            Format::Csv => writeln!(out, "{}", row.csv()).unwrap(),
+            Format::Json => rows.push(row),
+        }
+    }
+    if options.format == Format::Json {
+        writeln!(out, "{}", analyze::json(&rows)).unwrap();
+    }
+
+    let total = options.seeds.1.saturating_sub(options.seeds.0);
+    if total == 0 {
+        return
+    }
+    let percent = 100.0 * solved as f64 / total as f64;
+    match &options.agent {
+        Some(name) => eprintln!(
+            "{} agent playing {} drawing {}: won {} of {} ({:.1}%)",
+            name, variant.name, draw, solved, total, percent
+        ),
+        None => eprintln!(
+            "{} drawing {}: {} of {} solved ({:.1}%), {} unsolvable, {} exhausted, {} unknown",
+            variant.name, draw, solved, total, percent, unsolvable, exhausted, total - solved - unsolvable - exhausted
+        ),
+    }
+}
+
+/// Replay a record, exiting with an error if it doesn't hold up
+fn check(path: &str) {
+    let checked = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| analyze::check(&text, &read_variants()));
+    match checked {
+        Ok(summary) => println!("{}: {}", path, summary),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        },
+    }
+}
@@ -0,0 +1,661 @@
+use std::collections::{HashSet, VecDeque};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use crate::game::{Card, CardFace, Suit, SUITS, new_deck};
+use crate::variant::{Variant, BuildRules, Direction, EmptyRule, PileDef, PileKind, SuitRule};
+
+/// A pile on the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pile {
+    Waste,
+    Reserve,
+    Foundation(usize),
+    Tableau(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Move {
+    /// Flip cards from the stock onto the waste
+    Draw,
+    /// Turn the waste back over to make a new stock
+    Recycle,
+    /// Move the top `count` cards of one pile onto another
+    Cards {
+        from: Pile,
+        to: Pile,
+        count: usize,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Foundation {
+    pub suit: Suit,
+    pub rules: BuildRules,
+    pub cards: Vec<Card>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tableau {
+    pub rules: BuildRules,
+    /// Bottom card first
+    pub cards: Vec<(Card, CardFace)>,
+}
+
+/// A game of solitaire without any of the entities used to draw it, so it can be searched and played headless
+#[derive(Debug, Clone)]
+pub struct Board {
+    /// The next card to be drawn is at the end
+    pub stock: Vec<Card>,
+    /// The top card is at the end
+    pub waste: Vec<Card>,
+    /// The top card is at the end
+    pub reserve: Vec<(Card, CardFace)>,
+    pub foundations: Vec<Foundation>,
+    pub tableau: Vec<Tableau>,
+    /// Number of times the waste has been turned back over into the stock
+    pub recycles: usize,
+    /// Cards flipped from the stock on each draw
+    pub draw: usize,
+    /// Passes through the stock, `None` if there is no limit
+    pub passes: Option<usize>,
+    pub single_card_moves: bool,
+    /// Empty tableau piles are refilled from the reserve
+    pub auto_fill: bool,
+    /// The top card of a foundation can be moved back onto the tableau
+    pub foundation_moves: bool,
+    /// Taking a card back off a foundation costs points
+    pub take_back_penalty: bool,
+}
+
+/// All of the decks used by a variant, shuffled the same way every time for a given seed
+pub fn shuffled_deck(decks: usize, seed: u64) -> Vec<Card> {
+    let mut deck: Vec<Card> = (0..decks).flat_map(|_| new_deck()).collect();
+    deck.shuffle(&mut StdRng::seed_from_u64(seed));
+    deck
+}
+
+/// Deal one layer at a time across a group of piles, following the face up/down pattern of each pile
+fn deal_layers(deck: &mut Vec<Card>, def: &PileDef, open: bool) -> Vec<Vec<(Card, CardFace)>> {
+    let patterns: Vec<Vec<CardFace>> = (0..def.count).map(|i| def.pattern(i)).collect();
+    let mut piles = vec![Vec::new(); def.count];
+    let layers = patterns.iter().map(|pattern| pattern.len()).max().unwrap_or(0);
+    for layer in 0..layers {
+        for (pile, pattern) in piles.iter_mut().zip(patterns.iter()) {
+            if let Some(face) = pattern.get(layer) {
+                let face = if open {CardFace::Up} else {*face};
+                pile.push((deck.pop().unwrap(), face));
+            }
+        }
+    }
+    piles
+}
+
+impl Board {
+    /// Deal a shuffled deck onto the piles of the variant, taking cards from the end of the deck.
+    /// An open deal turns every card face up
+    pub fn deal(variant: &Variant, draw: usize, passes: Option<usize>, open: bool, mut deck: Vec<Card>) -> Self {
+        let mut board = Board {
+            stock: Vec::new(),
+            waste: Vec::new(),
+            reserve: Vec::new(),
+            foundations: Vec::new(),
+            tableau: Vec::new(),
+            recycles: 0,
+            draw,
+            passes,
+            single_card_moves: variant.single_card_moves,
+            auto_fill: variant.auto_fill,
+            foundation_moves: variant.foundation_moves,
+            take_back_penalty: false,
+        };
+        for pile in variant.piles.iter() {
+            match pile.kind {
+                PileKind::Stock | PileKind::Waste => {},
+                PileKind::Reserve => {
+                    board.reserve = deal_layers(&mut deck, pile, open).remove(0);
+                },
+                PileKind::Foundation => {
+                    // Validated when the variant is loaded
+                    let mut rules = pile.build.unwrap();
+                    // The first card dealt decides the starting rank of every completed pile
+                    let mut first = None;
+                    if rules.empty == EmptyRule::FirstDealt {
+                        let card = deck.pop().unwrap();
+                        rules.empty = EmptyRule::Rank(card.kind);
+                        first = Some(card);
+                    }
+                    for i in 0..pile.count {
+                        // Piles are split evenly between the suits in texture order
+                        let suit = SUITS[i * 4 / pile.count];
+                        let mut cards = Vec::new();
+                        if let Some(card) = first.filter(|card| card.suit == suit) {
+                            cards.push(card);
+                            first = None;
+                        }
+                        board.foundations.push(Foundation {suit, rules, cards});
+                    }
+                    let start = board.foundations.len() - pile.count;
+                    for (foundation, cards) in board.foundations[start..].iter_mut().zip(deal_layers(&mut deck, pile, open)) {
+                        foundation.cards.extend(cards.into_iter().map(|(card, _)| card));
+                    }
+                },
+                PileKind::Tableau => {
+                    let rules = pile.build.unwrap();
+                    for cards in deal_layers(&mut deck, pile, open) {
+                        board.tableau.push(Tableau {rules, cards});
+                    }
+                },
+            }
+        }
+        board.stock = deck;
+        board
+    }
+
+    pub fn top(&self, pile: Pile) -> Option<Card> {
+        match pile {
+            Pile::Waste => self.waste.last().copied(),
+            Pile::Reserve => self.reserve.last().map(|(card, _)| *card),
+            Pile::Foundation(i) => self.foundations[i].cards.last().copied(),
+            Pile::Tableau(i) => self.tableau[i].cards.last().map(|(card, _)| *card),
+        }
+    }
+
+    /// Cards in a pile from the bottom up
+    pub fn cards(&self, pile: Pile) -> Vec<Card> {
+        match pile {
+            Pile::Waste => self.waste.clone(),
+            Pile::Reserve => self.reserve.iter().map(|(card, _)| *card).collect(),
+            Pile::Foundation(i) => self.foundations[i].cards.clone(),
+            Pile::Tableau(i) => self.tableau[i].cards.iter().map(|(card, _)| *card).collect(),
+        }
+    }
+
+    /// Number of face up cards at the top of a tableau pile that can be moved together
+    pub fn run_len(&self, i: usize) -> usize {
+        let tableau = &self.tableau[i];
+        let mut len = 0;
+        for (n, (card, face)) in tableau.cards.iter().enumerate().rev() {
+            if *face == CardFace::Down {
+                break
+            }
+            if len > 0 && !tableau.rules.accepts(Some(card), tableau.cards[n + 1].0) {
+                break
+            }
+            len += 1;
+        }
+        if self.single_card_moves {len.min(1)} else {len}
+    }
+
+    /// Return true if `card`, with `count - 1` cards on top of it, can be placed on the pile
+    pub fn accepts(&self, to: Pile, card: Card, count: usize) -> bool {
+        match to {
+            Pile::Foundation(i) => {
+                let foundation = &self.foundations[i];
+                count == 1 && foundation.suit == card.suit && foundation.rules.accepts(foundation.cards.last(), card)
+            },
+            Pile::Tableau(i) => self.tableau[i].rules.accepts(self.tableau[i].cards.last().map(|(card, _)| card), card),
+            Pile::Waste | Pile::Reserve => false,
+        }
+    }
+
+    pub fn can_recycle(&self) -> bool {
+        self.stock.is_empty() && !self.waste.is_empty() && self.passes.map(|passes| self.recycles + 1 < passes).unwrap_or(true)
+    }
+
+    /// Every legal move in the position
+    pub fn moves(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let destinations: Vec<Pile> = (0..self.foundations.len()).map(Pile::Foundation)
+            .chain((0..self.tableau.len()).map(Pile::Tableau))
+            .collect();
+
+        let mut sources = Vec::new();
+        if let Some(card) = self.top(Pile::Waste) {
+            sources.push((Pile::Waste, card, 1));
+        }
+        if let Some(card) = self.top(Pile::Reserve) {
+            sources.push((Pile::Reserve, card, 1));
+        }
+        for (i, tableau) in self.tableau.iter().enumerate() {
+            for count in 1..=self.run_len(i) {
+                sources.push((Pile::Tableau(i), tableau.cards[tableau.cards.len() - count].0, count));
+            }
+        }
+        if self.foundation_moves {
+            for i in 0..self.foundations.len() {
+                if let Some(card) = self.top(Pile::Foundation(i)) {
+                    sources.push((Pile::Foundation(i), card, 1));
+                }
+            }
+        }
+        for (from, card, count) in sources {
+            for to in destinations.iter() {
+                // Cards only come off a foundation to be built on in the tableau
+                if matches!((from, to), (Pile::Foundation(_), Pile::Foundation(_))) {
+                    continue
+                }
+                if *to != from && self.accepts(*to, card, count) {
+                    moves.push(Move::Cards {from, to: *to, count});
+                }
+            }
+        }
+
+        if !self.stock.is_empty() {
+            moves.push(Move::Draw);
+        } else if self.can_recycle() {
+            moves.push(Move::Recycle);
+        }
+        moves
+    }
+
+    /// Make a legal move, turning over any card it uncovers and refilling tableau piles from the reserve
+    pub fn apply(&mut self, mv: Move) {
+        match mv {
+            Move::Draw => {
+                for _ in 0..self.draw {
+                    match self.stock.pop() {
+                        Some(card) => self.waste.push(card),
+                        None => break,
+                    }
+                }
+            },
+            Move::Recycle => {
+                self.stock = self.waste.drain(..).rev().collect();
+                self.recycles += 1;
+            },
+            Move::Cards {from, to, count} => {
+                let cards = self.take(from, count);
+                match to {
+                    Pile::Foundation(i) => self.foundations[i].cards.extend(cards),
+                    Pile::Tableau(i) => self.tableau[i].cards.extend(cards.into_iter().map(|card| (card, CardFace::Up))),
+                    Pile::Waste | Pile::Reserve => panic!("cards can't be moved onto the {:?}", to),
+                }
+                if self.auto_fill {
+                    for tableau in self.tableau.iter_mut().filter(|tableau| tableau.cards.is_empty()) {
+                        if let Some((card, _)) = self.reserve.pop() {
+                            tableau.cards.push((card, CardFace::Up));
+                        }
+                    }
+                    if let Some((_, face)) = self.reserve.last_mut() {
+                        *face = CardFace::Up;
+                    }
+                }
+            },
+        }
+    }
+
+    /// Remove the top cards of a pile, turning over whatever is left on top
+    fn take(&mut self, from: Pile, count: usize) -> Vec<Card> {
+        match from {
+            Pile::Waste => {
+                let at = self.waste.len() - count;
+                self.waste.split_off(at)
+            },
+            Pile::Reserve => {
+                let at = self.reserve.len() - count;
+                let cards = self.reserve.split_off(at).into_iter().map(|(card, _)| card).collect();
+                if let Some((_, face)) = self.reserve.last_mut() {
+                    *face = CardFace::Up;
+                }
+                cards
+            },
+            Pile::Foundation(i) => {
+                let cards = &mut self.foundations[i].cards;
+                cards.split_off(cards.len() - count)
+            },
+            Pile::Tableau(i) => {
+                let cards = &mut self.tableau[i].cards;
+                let taken = cards.split_off(cards.len() - count).into_iter().map(|(card, _)| card).collect();
+                if let Some((_, face)) = cards.last_mut() {
+                    *face = CardFace::Up;
+                }
+                taken
+            },
+        }
+    }
+
+    /// Points for making a move, in the usual Windows scoring. Any card onto a foundation is worth 10, a card from
+    /// the waste or reserve onto the tableau 5 and turning over a tableau card another 5. Turning the waste back
+    /// over costs 100 when drawing one and 20 when drawing more, and taking a card back off a foundation 15 if
+    /// the penalty is on
+    pub fn points(&self, mv: Move) -> i32 {
+        match mv {
+            Move::Draw => 0,
+            Move::Recycle => if self.draw == 1 {-100} else {-20},
+            Move::Cards {from, to, count} => {
+                let mut points = match (from, to) {
+                    (_, Pile::Foundation(_)) => 10,
+                    (Pile::Waste | Pile::Reserve, Pile::Tableau(_)) => 5,
+                    (Pile::Foundation(_), _) if self.take_back_penalty => -15,
+                    _ => 0,
+                };
+                if let Pile::Tableau(i) = from {
+                    let cards = &self.tableau[i].cards;
+                    if cards.len() > count && cards[cards.len() - count - 1].1 == CardFace::Down {
+                        points += 5;
+                    }
+                }
+                points
+            },
+        }
+    }
+
+    /// Score after playing the moves from this position, never going below zero
+    pub fn score(&self, moves: &[Move]) -> i32 {
+        let mut board = self.clone();
+        let mut score = 0;
+        for mv in moves {
+            score = (score + board.points(*mv)).max(0);
+            board.apply(*mv);
+        }
+        score
+    }
+
+    pub fn is_won(&self) -> bool {
+        self.foundations.iter().all(|foundation| foundation.cards.len() == 13)
+    }
+
+    /// Cards on the foundations, cards not yet turned over, and cards still in the stock, waste or reserve
+    fn progress(&self) -> (usize, usize, usize) {
+        let face_down = |cards: &[(Card, CardFace)]| cards.iter().filter(|(_, face)| *face == CardFace::Down).count();
+        (
+            self.foundations.iter().map(|foundation| foundation.cards.len()).sum(),
+            face_down(&self.reserve) + self.tableau.iter().map(|tableau| face_down(&tableau.cards)).sum::<usize>(),
+            self.stock.len() + self.waste.len() + self.reserve.len(),
+        )
+    }
+
+    /// Return true if nothing that can be done from here gets anywhere: however the stock is gone through and
+    /// the table is rearranged, no more cards reach the foundations, get turned over or come off the stock,
+    /// waste or reserve. Gives up and returns false after looking at `limit` positions
+    pub fn is_stuck(&self, limit: usize) -> bool {
+        if self.is_won() {
+            return false
+        }
+        let (foundations, face_down, left) = self.progress();
+        let mut seen = HashSet::from([self.key()]);
+        let mut queue = VecDeque::from([self.clone()]);
+        while let Some(board) = queue.pop_front() {
+            for mv in board.moves() {
+                let mut next = board.clone();
+                next.apply(mv);
+                let (f, d, l) = next.progress();
+                if f > foundations || d < face_down || l < left {
+                    return false
+                }
+                if seen.insert(next.key()) {
+                    if seen.len() > limit {
+                        return false
+                    }
+                    queue.push_back(next);
+                }
+            }
+        }
+        true
+    }
+
+    /// A move to a foundation that can't get in the way of winning, because every card that
+    /// could still be built on it in the tableau is already on a foundation
+    pub fn safe_foundation_move(&self) -> Option<Move> {
+        let rules = self.tableau.first()?.rules;
+        if rules.direction != Direction::Descending || rules.wrap {
+            return None
+        }
+        let mut sources = vec![Pile::Waste, Pile::Reserve];
+        sources.extend((0..self.tableau.len()).map(Pile::Tableau));
+        for from in sources {
+            let card = match self.top(from) {
+                Some(card) => card,
+                None => continue,
+            };
+            let to = match (0..self.foundations.len()).find(|i| self.accepts(Pile::Foundation(*i), card, 1)) {
+                Some(i) => i,
+                None => continue,
+            };
+            let foundation = &self.foundations[to];
+            if foundation.rules.direction != Direction::Ascending || foundation.rules.wrap {
+                continue
+            }
+            // Cards one rank lower are the only ones that could be built on this one
+            let rank = card.kind.column();
+            let safe = rank <= 1 || self.foundations.iter()
+                .filter(|foundation| match rules.suit {
+                    SuitRule::AlternateColour => card.suit.can_stack(&foundation.suit),
+                    SuitRule::SameSuit => card.suit == foundation.suit,
+                    SuitRule::Any => true,
+                })
+                .all(|foundation| foundation.cards.len() >= rank);
+            if safe {
+                return Some(Move::Cards {from, to: Pile::Foundation(to), count: 1})
+            }
+        }
+        None
+    }
+
+    /// Hash of the position, treating tableau piles that have been swapped around as the same
+    pub fn key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.stock.hash(&mut hasher);
+        self.waste.hash(&mut hasher);
+        self.reserve.hash(&mut hasher);
+        for foundation in self.foundations.iter() {
+            foundation.cards.len().hash(&mut hasher);
+        }
+        let mut piles: Vec<u64> = self.tableau.iter().map(|tableau| {
+            let mut hasher = DefaultHasher::new();
+            tableau.cards.hash(&mut hasher);
+            hasher.finish()
+        }).collect();
+        piles.sort_unstable();
+        piles.hash(&mut hasher);
+        // With unlimited passes going around the stock again doesn't make a new position
+        if self.passes.is_some() {
+            self.recycles.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+/// Variants, positions and cards shared by the tests
+#[cfg(test)]
+pub mod fixtures {
+    use super::*;
+    use crate::notation::parse_card;
+    use crate::variant::read_variants;
+
+    pub fn variant(name: &str) -> Variant {
+        read_variants().into_iter().find(|variant| variant.name == name).unwrap()
+    }
+
+    /// Klondike drawing one with no limit on passes
+    pub fn klondike(seed: u64) -> Board {
+        Board::deal(&variant("Klondike"), 1, None, false, shuffled_deck(1, seed))
+    }
+
+    /// A Klondike table with nothing on it, for setting up positions by hand
+    pub fn empty_board() -> Board {
+        let mut board = klondike(1);
+        board.stock.clear();
+        board.waste.clear();
+        for foundation in board.foundations.iter_mut() {
+            foundation.cards.clear();
+        }
+        for tableau in board.tableau.iter_mut() {
+            tableau.cards.clear();
+        }
+        board
+    }
+
+    /// An empty table with each foundation built up to the card in `column`, 0 for the ace
+    pub fn built_up(column: usize) -> Board {
+        let mut board = empty_board();
+        let mut deck = new_deck();
+        deck.sort_by_key(|card| card.kind.column());
+        for foundation in board.foundations.iter_mut() {
+            foundation.cards = deck.iter().filter(|card| card.suit == foundation.suit && card.kind.column() <= column).copied().collect();
+        }
+        board
+    }
+
+    pub fn card(code: &str) -> Card {
+        parse_card(code).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::fixtures::*;
+    use crate::game::{DrawMode, StockPasses};
+
+    /// Draw through the stock and turn the waste back over for as long as that is allowed
+    fn passes_through_stock(draw: usize, passes: Option<usize>) -> usize {
+        let mut board = Board::deal(&variant("Klondike"), draw, passes, false, shuffled_deck(1, 1));
+        let mut count = 1;
+        loop {
+            while !board.stock.is_empty() {
+                board.apply(Move::Draw);
+            }
+            if !board.moves().contains(&Move::Recycle) {
+                assert!(!board.can_recycle());
+                return count
+            }
+            board.apply(Move::Recycle);
+            count += 1;
+        }
+    }
+
+    #[test]
+    fn vegas_recycle_limits() {
+        let klondike = variant("Klondike");
+        assert_eq!(passes_through_stock(1, klondike.pass_limit(&DrawMode::Draw1, &StockPasses::Vegas)), 1);
+        assert_eq!(passes_through_stock(3, klondike.pass_limit(&DrawMode::Draw3, &StockPasses::Vegas)), 3);
+    }
+
+    #[test]
+    fn safe_foundation_move_waits_for_opposite_colour() {
+        let mut board = empty_board();
+        board.foundations[0].cards = vec![card("As"), card("2s")];
+        board.foundations[1].cards = vec![card("Ac")];
+        board.foundations[2].cards = vec![card("Ad")];
+        board.foundations[3].cards = vec![card("Ah")];
+        board.tableau[0].cards = vec![(card("3s"), CardFace::Up)];
+        board.tableau[1].cards = vec![(card("2h"), CardFace::Down), (card("9c"), CardFace::Up)];
+        board.tableau[2].cards = vec![(card("2d"), CardFace::Up)];
+        let up = |from, to| Move::Cards {from: Pile::Tableau(from), to: Pile::Foundation(to), count: 1};
+        assert_eq!(board.safe_foundation_move(), Some(up(2, 2)));
+        board.apply(up(2, 2));
+        // The 2h could still want to go on the 3s
+        assert_eq!(board.safe_foundation_move(), None);
+        board.tableau[1].cards = vec![(card("2h"), CardFace::Up)];
+        assert_eq!(board.safe_foundation_move(), Some(up(1, 3)));
+        board.apply(up(1, 3));
+        assert_eq!(board.safe_foundation_move(), Some(up(0, 0)));
+    }
+
+    #[test]
+    fn take_back_penalty() {
+        let mut board = empty_board();
+        board.foundations[0].cards = vec![card("As")];
+        board.foundations[1].cards = vec![card("Ac")];
+        board.waste = vec![card("2c"), card("2s")];
+        board.tableau[0].cards = vec![(card("3h"), CardFace::Up)];
+        let take_back = Move::Cards {from: Pile::Foundation(0), to: Pile::Tableau(0), count: 1};
+        let moves = [
+            Move::Cards {from: Pile::Waste, to: Pile::Foundation(0), count: 1},
+            Move::Cards {from: Pile::Waste, to: Pile::Foundation(1), count: 1},
+            take_back,
+        ];
+        assert_eq!(board.score(&moves), 20);
+        board.take_back_penalty = true;
+        assert_eq!(board.score(&moves), 5);
+        // Never below zero
+        board.foundations[0].cards.push(card("2s"));
+        assert_eq!(board.points(take_back), -15);
+        assert_eq!(board.score(&[take_back]), 0);
+    }
+
+    #[test]
+    fn stuck_positions() {
+        let mut board = empty_board();
+        board.tableau[0].cards = vec![(card("5d"), CardFace::Down), (card("5s"), CardFace::Up)];
+        board.tableau[1].cards = vec![(card("6c"), CardFace::Up)];
+        assert!(board.is_stuck(1000));
+        // Moving the 5s turns over the 5d
+        board.tableau[1].cards = vec![(card("6h"), CardFace::Up)];
+        assert!(!board.is_stuck(1000));
+        // Moving the jack back and forth gets nowhere
+        board.tableau[0].cards = vec![(card("Js"), CardFace::Up)];
+        board.tableau[1].cards = vec![(card("Qh"), CardFace::Up)];
+        board.tableau[2].cards = vec![(card("Qd"), CardFace::Up)];
+        assert!(board.is_stuck(1000));
+        board.waste = vec![card("Ah")];
+        assert!(!board.is_stuck(1000));
+    }
+
+    #[test]
+    fn open_deal() {
+        for name in ["Klondike", "Canfield"] {
+            let closed = Board::deal(&variant(name), 3, None, false, shuffled_deck(1, 9));
+            let open = Board::deal(&variant(name), 3, None, true, shuffled_deck(1, 9));
+            let faces = |board: &Board| board.tableau.iter()
+                .flat_map(|tableau| tableau.cards.iter())
+                .chain(board.reserve.iter())
+                .map(|(_, face)| *face)
+                .collect::<Vec<_>>();
+            assert!(faces(&closed).contains(&CardFace::Down));
+            assert!(faces(&open).iter().all(|face| *face == CardFace::Up));
+            // The same cards in the same places
+            for pile in (0..open.tableau.len()).map(Pile::Tableau).chain([Pile::Reserve]) {
+                assert_eq!(open.cards(pile), closed.cards(pile));
+            }
+            assert_eq!(open.stock, closed.stock);
+        }
+    }
+
+    #[test]
+    fn forty_thieves_deal() {
+        let board = Board::deal(&variant("Forty Thieves"), 1, Some(1), false, shuffled_deck(2, 7));
+        assert_eq!(board.tableau.len(), 10);
+        assert!(board.tableau.iter().all(|tableau| tableau.cards.len() == 4 && tableau.cards.iter().all(|(_, face)| *face == CardFace::Up)));
+        assert_eq!(board.foundations.len(), 8);
+        assert!(board.foundations.iter().all(|foundation| foundation.cards.is_empty()));
+        // Two foundations for each suit
+        assert!(SUITS.iter().all(|suit| board.foundations.iter().filter(|foundation| foundation.suit == *suit).count() == 2));
+        assert_eq!(board.stock.len(), 64);
+        assert!(board.reserve.is_empty() && board.waste.is_empty());
+        // Every card of both decks is dealt once
+        let mut dealt: Vec<Card> = board.tableau.iter()
+            .flat_map(|tableau| tableau.cards.iter().map(|(card, _)| *card))
+            .chain(board.stock.iter().copied())
+            .collect();
+        assert_eq!(dealt.len(), 104);
+        let mut decks: Vec<Card> = new_deck().into_iter().chain(new_deck()).collect();
+        let order = |card: &Card| (card.suit as usize, card.kind.column());
+        dealt.sort_by_key(order);
+        decks.sort_by_key(order);
+        assert_eq!(dealt, decks);
+    }
+
+    #[test]
+    fn single_card_moves() {
+        let mut board = Board::deal(&variant("Forty Thieves"), 1, Some(1), false, shuffled_deck(2, 7));
+        board.stock.clear();
+        for tableau in board.tableau.iter_mut() {
+            tableau.cards.clear();
+        }
+        board.tableau[0].cards = vec![(card("Kh"), CardFace::Up), (card("9s"), CardFace::Up), (card("8s"), CardFace::Up)];
+        board.tableau[1].cards = vec![(card("Ts"), CardFace::Up)];
+        let pair = Move::Cards {from: Pile::Tableau(0), to: Pile::Tableau(1), count: 2};
+        assert_eq!(board.run_len(0), 1);
+        assert!(!board.moves().contains(&pair));
+        // The eight still moves on its own, into a space
+        assert!(board.moves().contains(&Move::Cards {from: Pile::Tableau(0), to: Pile::Tableau(2), count: 1}));
+        board.single_card_moves = false;
+        assert_eq!(board.run_len(0), 2);
+        assert!(board.moves().contains(&pair));
+    }
+}
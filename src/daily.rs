@@ -0,0 +1,325 @@
+//! One deal a day, the same for everyone, with the results kept on disk.
+//!
+//! The seed comes from the date in UTC so players anywhere get the same deal on the same day,
+//! and the rules are always Klondike drawing one with unlimited passes
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Deal, FontHandle, GameClock, GameStats};
+use crate::storage::data_dir;
+
+pub const DAILY_VARIANT: &str = "Klondike";
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    pub fn today() -> Self {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default();
+        Self::from_days((secs / 86400) as i64)
+    }
+
+    /// Date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    pub fn from_days(days: i64) -> Self {
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 {mp + 3} else {mp - 9} as u32;
+        let year = yoe + era * 400 + if month <= 2 {1} else {0};
+        Self {year, month, day}
+    }
+
+    /// Days since 1970-01-01
+    pub fn days(&self) -> i64 {
+        let year = if self.month <= 2 {self.year - 1} else {self.year};
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = (self.month as i64 + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    /// 0 for Monday through 6 for Sunday
+    pub fn weekday(&self) -> usize {
+        (self.days() + 3).rem_euclid(7) as usize
+    }
+
+    /// The deal for the day, readable as the date itself
+    pub fn seed(&self) -> u64 {
+        (self.year as u64) * 10000 + (self.month as u64) * 100 + self.day as u64
+    }
+
+    fn first_of_month(&self) -> Self {
+        Self {day: 1, ..*self}
+    }
+
+    fn next_month(&self) -> Self {
+        if self.month == 12 {Self {year: self.year + 1, month: 1, day: 1}} else {Self {month: self.month + 1, day: 1, ..*self}}
+    }
+
+    fn prev_month(&self) -> Self {
+        if self.month == 1 {Self {year: self.year - 1, month: 12, day: 1}} else {Self {month: self.month - 1, day: 1, ..*self}}
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// How a daily deal went. Only the first win counts
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct DailyResult {
+    pub won: bool,
+    /// Time taken to win
+    pub seconds: u64,
+    pub moves: usize,
+}
+
+/// Results of every daily deal played, by date
+#[derive(Debug, Default, Resource)]
+pub struct DailyResults(pub BTreeMap<String, DailyResult>);
+
+impl DailyResults {
+    fn path() -> std::path::PathBuf {
+        data_dir().join("daily.ron")
+    }
+
+    pub fn load() -> Self {
+        let results = fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|text| ron::from_str(&text).map_err(|e| error!("Failed to read the daily results: {}", e)).ok())
+            .unwrap_or_default();
+        Self(results)
+    }
+
+    fn save(&self) {
+        let path = Self::path();
+        let text = ron::ser::to_string_pretty(&self.0, Default::default()).unwrap();
+        if let Err(e) = fs::create_dir_all(data_dir()).and_then(|_| fs::write(&path, text)) {
+            error!("Failed to save the daily results to {}: {}", path.display(), e);
+        }
+    }
+
+    pub fn get(&self, date: Date) -> Option<&DailyResult> {
+        self.0.get(&date.to_string())
+    }
+}
+
+/// Deal today's game next instead of shuffling
+#[derive(Debug, Resource)]
+pub struct DailyRequested;
+
+/// Month shown in the calendar on the main menu
+#[derive(Debug, Resource)]
+pub struct CalendarMonth(pub Date);
+
+impl Default for CalendarMonth {
+    fn default() -> Self {
+        Self(Date::today().first_of_month())
+    }
+}
+
+#[derive(Component)]
+pub struct CalendarRoot;
+
+#[derive(Component)]
+pub enum CalendarButton {
+    Prev,
+    Next,
+}
+
+/// Note that a daily deal has been started, so it shows on the calendar even if it isn't won
+pub fn daily_started_system(deal: Option<Res<Deal>>, mut results: ResMut<DailyResults>) {
+    let deal = if let Some(d) = deal {d} else {return};
+    if !deal.is_changed() {
+        return
+    }
+    if let Some(date) = deal.daily {
+        if results.get(date).is_none() {
+            results.0.insert(date.to_string(), DailyResult::default());
+            results.save();
+        }
+    }
+}
+
+/// Keep the time and moves of the first win of a daily deal
+pub fn daily_won_system(deal: Option<Res<Deal>>, clock: Res<GameClock>, stats: Res<GameStats>, mut results: ResMut<DailyResults>) {
+    let date = if let Some(date) = deal.and_then(|deal| deal.daily) {date} else {return};
+    let result = results.0.entry(date.to_string()).or_default();
+    if result.won {
+        return
+    }
+    *result = DailyResult {
+        won: true,
+        seconds: clock.0.elapsed().as_secs(),
+        moves: stats.moves,
+    };
+    info!("Daily deal for {} won in {}s and {} moves", date, result.seconds, result.moves);
+    results.save();
+}
+
+fn calendar_text(text: &str, font: &Handle<Font>, size: f32, color: Color) -> TextBundle {
+    TextBundle::from_section(text, TextStyle {font: font.clone(), font_size: size, color})
+}
+
+fn spawn_calendar(commands: &mut Commands, font: &Handle<Font>, month: Date, results: &DailyResults) {
+    let today = Date::today();
+    let cell = |color: Color| NodeBundle {
+        style: Style {
+            width: Val::Px(40.0),
+            height: Val::Px(32.0),
+            margin: UiRect::all(Val::Px(1.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        background_color: color.into(),
+        ..Default::default()
+    };
+    let row = || NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(20.0),
+                top: Val::Px(20.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            background_color: Color::rgba(0.1, 0.1, 0.1, 0.8).into(),
+            ..Default::default()
+        })
+        .insert(CalendarRoot)
+        .with_children(|parent| {
+            parent.spawn(row()).with_children(|parent| {
+                for (label, button) in [("<", CalendarButton::Prev), (">", CalendarButton::Next)] {
+                    if let CalendarButton::Next = button {
+                        let title = format!("{} {}", MONTHS[month.month as usize - 1], month.year);
+                        parent.spawn(NodeBundle {
+                                style: Style {width: Val::Px(206.0), justify_content: JustifyContent::Center, ..Default::default()},
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn(calendar_text(&title, font, 26.0, Color::WHITE));
+                            });
+                    }
+                    parent.spawn(ButtonBundle {
+                            style: Style {
+                                width: Val::Px(40.0),
+                                height: Val::Px(32.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                            ..Default::default()
+                        })
+                        .insert(button)
+                        .with_children(|parent| {
+                            parent.spawn(calendar_text(label, font, 26.0, Color::WHITE));
+                        });
+                }
+            });
+            parent.spawn(row()).with_children(|parent| {
+                for day in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"] {
+                    parent.spawn(cell(Color::NONE)).with_children(|parent| {
+                        parent.spawn(calendar_text(day, font, 18.0, Color::GRAY));
+                    });
+                }
+            });
+            let first = month.first_of_month();
+            let days = month.next_month().days() - first.days();
+            let mut day = 0;
+            while day < days {
+                parent.spawn(row()).with_children(|parent| {
+                    for weekday in 0..7 {
+                        if (day == 0 && weekday < first.weekday()) || day >= days {
+                            parent.spawn(cell(Color::NONE));
+                            continue
+                        }
+                        let date = Date::from_days(first.days() + day);
+                        let color = match results.get(date) {
+                            Some(result) if result.won => Color::rgb(0.2, 0.6, 0.2),
+                            Some(_) => Color::rgb(0.7, 0.5, 0.1),
+                            None => Color::rgb(0.3, 0.3, 0.3),
+                        };
+                        let text_color = if date == today {Color::GOLD} else {Color::WHITE};
+                        parent.spawn(cell(color)).with_children(|parent| {
+                            parent.spawn(calendar_text(&date.day.to_string(), font, 20.0, text_color));
+                        });
+                        day += 1;
+                    }
+                });
+            }
+            let summary = match results.get(today) {
+                Some(result) if result.won => format!(
+                    "Today: won in {}:{:02}, {} moves", result.seconds / 60, result.seconds % 60, result.moves
+                ),
+                Some(_) => "Today: not won yet".to_string(),
+                None => "Today: not played".to_string(),
+            };
+            parent.spawn(calendar_text(&summary, font, 20.0, Color::WHITE));
+        });
+}
+
+/// Rebuild the calendar when the menu is shown or the month changes
+pub fn calendar_system(
+    mut commands: Commands,
+    font: Res<FontHandle>,
+    mut month: ResMut<CalendarMonth>,
+    results: Res<DailyResults>,
+    q_root: Query<Entity, With<CalendarRoot>>,
+    interaction_query: Query<(&CalendarButton, &Interaction), Changed<Interaction>>,
+) {
+    for (button, interaction) in interaction_query.iter() {
+        if *interaction == Interaction::Pressed {
+            month.0 = match button {
+                CalendarButton::Prev => month.0.prev_month(),
+                CalendarButton::Next => month.0.next_month(),
+            };
+        }
+    }
+    if !month.is_changed() && !results.is_changed() && !q_root.is_empty() {
+        return
+    }
+    for entity in q_root.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_calendar(&mut commands, &font.0, month.0, &results);
+}
+
+pub fn hide_calendar(mut commands: Commands, q_root: Query<Entity, With<CalendarRoot>>) {
+    for entity in q_root.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
@@ -1,26 +1,53 @@
-use std::collections::HashMap;
-use std::time::Duration;
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use bevy::prelude::*;
 use bevy::render::camera::ScalingMode;
-use bevy::render::view::Visibility;
 use bevy::ui::Display;
+use bevy::ecs::system::SystemParam;
+use bevy::time::Stopwatch;
 use bevy_easings::*;
 // use bevy_easings::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
 use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::Deserialize;
 
 use crate::mouse_input::{Droppable, Draggable, Clickable, MouseInteraction};
-use crate::menus::{ResetMenuRoot, ResetButton};
+use crate::menus::{ResetMenuRoot, ResetButton, spawn_notice};
+use crate::variant::{Variant, GameRules, BuildRules, PileKind, Slot};
+use crate::board::{Board, Pile, Move, shuffled_deck};
+use crate::agent::{GreedyAgent, Observation, SolitaireAgent};
+use crate::solver::solve;
+use crate::notation::{GameRecord, format_layout, parse_layout};
+use crate::tree::MoveTree;
+use crate::storage::data_dir;
+use crate::daily::{Date, DailyRequested};
+use crate::game_over::StuckCheck;
+use crate::history::Navigate;
 
 #[allow(dead_code)] pub const BACK_GREEN: usize = 5 * 13;
 pub const BACK_BLUE: usize = 6 * 13;
 #[allow(dead_code)] pub const BACK_RED: usize = 7 * 13;
 pub const FINAL_STACKS: usize = 7 * 13 + 9;
 pub const EMPTY_SPACE: usize = 6 * 13 + 12;
+/// Tint of the empty space under the deck once it can't be turned over again
+pub const NO_REDEALS_COLOR: Color = Color::rgb(0.8, 0.2, 0.2);
 
 pub const CARD_WIDTH: f32 = 140.0;
 pub const CARD_HEIGHT: f32 = 190.0;
 pub const CARD_STACK_SPACE: f32 = 35.0;
 
+pub const WINDOW_WIDTH: f32 = 1280.0;
+pub const WINDOW_HEIGHT: f32 = 960.0;
+
+/// Time the solver gets to prove each candidate deal can be won
+pub const SOLVE_BUDGET: Duration = Duration::from_secs(2);
+/// Time spent looking for a winnable deal before dealing one that may not be
+pub const WINNABLE_SEARCH_LIMIT: Duration = Duration::from_secs(20);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Suit {
     Spades,
@@ -44,6 +71,9 @@ impl Suit {
     }
 }
 
+/// Suits in the order of their rows in the card texture
+pub const SUITS: [Suit; 4] = [Suit::Spades, Suit::Clubs, Suit::Diamonds, Suit::Hearts];
+
 impl Suit {
     pub fn row(&self) -> usize {
         match self {
@@ -55,7 +85,7 @@ impl Suit {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
 pub enum CardKind {
     Ace,
     Number(usize),
@@ -91,9 +121,26 @@ impl CardKind {
             CardKind::King => None,
         }
     }
+
+    /// The next rank up, wrapping around from King to Ace
+    pub fn next_wrapping(&self) -> CardKind {
+        self.next().unwrap_or(CardKind::Ace)
+    }
+
+    /// The next rank down, wrapping around from Ace to King
+    pub fn prev_wrapping(&self) -> CardKind {
+        match self {
+            CardKind::Ace => CardKind::King,
+            CardKind::Number(2) => CardKind::Ace,
+            CardKind::Number(n) => CardKind::Number(n - 1),
+            CardKind::Jack => CardKind::Number(10),
+            CardKind::Queen => CardKind::Jack,
+            CardKind::King => CardKind::Queen,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, Component, PartialEq)]
+#[derive(Debug, Clone, Copy, Component, PartialEq, Eq, Hash)]
 pub struct Card {
     pub suit: Suit,
     pub kind: CardKind,
@@ -103,11 +150,6 @@ impl Card {
     pub fn texture_index(&self) -> usize {
         (self.suit.row() * 13) + self.kind.column()
     }
-
-    /// Return true if other can be stacked below self
-    pub fn can_stack(&self, other: &Card) -> bool {
-        self.suit.can_stack(&other.suit) && self.kind.can_stack(&other.kind)
-    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -116,13 +158,30 @@ pub enum StackKind {
     Stack,
 }
 
+/// Build a full, unshuffled deck
+pub fn new_deck() -> Vec<Card> {
+    let mut deck = Vec::new();
+    for suit in [Suit::Spades, Suit::Clubs, Suit::Hearts, Suit::Diamonds] {
+        deck.push(Card {suit: suit, kind: CardKind::Ace});
+        for n in 2..=10 {
+            deck.push(Card {suit: suit, kind: CardKind::Number(n)});
+        }
+        deck.push(Card {suit: suit, kind: CardKind::Jack});
+        deck.push(Card {suit: suit, kind: CardKind::Queen});
+        deck.push(Card {suit: suit, kind: CardKind::King});
+    }
+    deck
+}
+
 #[derive(Debug, Component)]
 pub struct Deck {
     pub cards: Vec<Card>,
+    /// Number of times the discard pile has been turned back over into the deck
+    pub recycles: usize,
 }
 
 // XXX: Possibly change this to be a Card field instead of an individual component?
-#[derive(Debug, Clone, Copy, PartialEq, Component)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Component)]
 pub enum CardFace {
     Down,
     Up,
@@ -149,6 +208,19 @@ impl Area {
         pos.y >= self.pos.y &&
         pos.y <= (self.pos.y + self.size.y)
     }
+
+    /// Size of the area shared with another one
+    pub fn overlap(&self, other: &Area) -> f32 {
+        let min = self.pos.max(other.pos);
+        let max = (self.pos + self.size).min(other.pos + other.size);
+        let size = (max - min).max(Vec2::ZERO);
+        size.x * size.y
+    }
+
+    /// How far a point is from the nearest edge, zero if it's inside
+    pub fn distance(&self, pos: Vec2) -> f32 {
+        pos.distance(pos.clamp(self.pos, self.pos + self.size))
+    }
 }
 
 #[derive(Debug, PartialEq, Resource)]
@@ -166,47 +238,168 @@ impl DrawMode {
     }
 }
 
+/// Deal every card face up, as in Thoughtful Solitaire, so the whole game can be planned out
+#[derive(Debug, Default, Resource)]
+pub struct OpenDeal(pub bool);
+
+/// Limit on the number of passes through the deck for variants that don't set their own
+#[derive(Debug, Clone, Copy, PartialEq, Resource)]
+pub enum StockPasses {
+    Unlimited,
+    /// Three passes when drawing three and a single pass when drawing one
+    Vegas,
+}
+
+/// Taking a card back off a completed pile costs 15 points, as in Windows
+#[derive(Debug, Resource)]
+pub struct TakeBackPenalty(pub bool);
+
+/// Only deal games the solver has found a way to win
+#[derive(Debug, Default, Resource)]
+pub struct WinnableDeals(pub bool);
+
+/// The game currently being played, as it was first dealt
+#[derive(Debug, Clone, Resource)]
+pub struct Deal {
+    /// Seed the deck was shuffled with, `None` for an imported layout
+    pub seed: Option<u64>,
+    /// Whether every card was dealt face up
+    pub open: bool,
+    /// Date of the daily deal this is
+    pub daily: Option<Date>,
+    pub board: Board,
+    /// A winnable deal was asked for but none was found in time, so this one may not be
+    pub unverified: bool,
+}
+
+/// Time spent playing the current game
+#[derive(Debug, Default, Resource)]
+pub struct GameClock(pub Stopwatch);
+
+/// Progress through the current game, as shown on the HUD
+#[derive(Debug, Default, Resource)]
+pub struct GameStats {
+    pub moves: usize,
+    pub score: i32,
+    /// Passes through the stock left, `None` if there is no limit
+    pub passes_left: Option<usize>,
+}
+
+impl GameStats {
+    /// One line summary with the time taken
+    pub fn summary(&self, clock: &GameClock) -> String {
+        let secs = clock.0.elapsed().as_secs();
+        let mut summary = format!("Time {}:{:02}   Moves {}   Score {}", secs / 60, secs % 60, self.moves, self.score);
+        if let Some(passes) = self.passes_left {
+            summary.push_str(&format!("   Passes {}", passes));
+        }
+        summary
+    }
+}
+
+/// Text on the bar at the bottom of the table showing `GameStats`
+#[derive(Debug, Component)]
+pub struct HudText;
+
+/// Which cards are played onto the foundations by themselves
+#[derive(Debug, Default, Clone, Copy, PartialEq, Resource)]
+pub enum AutoPlay {
+    #[default]
+    Off,
+    /// Only cards that can no longer be needed to build on in the tableau
+    Safe,
+    /// Every card that fits on a foundation
+    Aggressive,
+}
+
+/// Send a clicked card to the best place it can go, instead of waiting for a double click
+#[derive(Debug, Default, Clone, Copy, PartialEq, Resource)]
+pub struct SmartClick(pub bool);
+
+/// How the piles a dragged card can go on are shown
+#[derive(Debug, Default, Clone, Copy, PartialEq, Resource)]
+pub enum DropHighlight {
+    Off,
+    /// Tint the card or space it would go on
+    #[default]
+    Tint,
+    /// Draw a border around it instead, which doesn't rely on telling colours apart
+    Outline,
+}
+
+/// Deal the current game again from the start instead of shuffling
+#[derive(Debug, Resource)]
+pub struct RestartRequested;
+
+/// Layout to deal the next game from instead of shuffling
+#[derive(Debug, Clone, Resource)]
+pub struct ImportedLayout(pub String);
+
+/// Which pile of the `Board` an entity is the base of
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct BoardPile(pub Pile);
+
+/// Deal being searched for in the background
+#[derive(Resource)]
+pub struct DealTask {
+    pub task: Task<Deal>,
+    /// Set to stop searching for a winnable deal
+    pub cancelled: Arc<AtomicBool>,
+}
+
 #[derive(Debug, Resource)]
 pub struct SolveTimer(pub Timer);
 
+/// Number of actions taken when the auto-solver last gave up or was stopped, so it is not retried until the player
+/// makes another move. Undoing lowers it rather than letting the auto-solver straight back in
+#[derive(Debug, Default, Resource)]
+pub struct AutoSolveBlocked(pub Option<usize>);
+
 #[derive(Debug, Default, Component)]
 pub struct DiscardPile;
 
+/// Cards laid out to show the order of the deck while it is hovered in an open deal
+#[derive(Debug, Component)]
+pub struct StockPreview;
+
+/// Label on the deck showing how many passes through it are left
+#[derive(Debug, Component)]
+pub struct PassesText;
+
+/// Canfield reserve pile. Only the top card is face up and it can only be played from, never onto
+#[derive(Debug, Default, Component)]
+pub struct Reserve;
+
 #[derive(Debug, Component, Clone)]
 pub struct Stack {
     pub kind: StackKind,
+    pub rules: BuildRules,
 }
 
 impl Stack {
-    pub fn new(kind: StackKind) -> Self {
+    pub fn new(kind: StackKind, rules: BuildRules) -> Self {
         Self {
-            kind: kind,
+            kind,
+            rules,
         }
     }
 
+    /// The rank on top of an ordered stack once it has all 13 cards
+    pub fn last_rank(&self) -> CardKind {
+        self.rules.last_rank()
+    }
+
     pub fn can_stack(&self, stack_card: Option<&Card>, target: Card, has_children: bool) -> bool {
         // info!("can_stack? stack={:?} stack_card={:?} target={:?}", self, stack_card, target);
         match self.kind {
             StackKind::Ordered(suit) => {
-                if has_children {
-                    false
-                } else if stack_card.is_none() && suit == target.suit && target.kind == CardKind::Ace {
-                    true
-                } else if suit == target.suit && Some(target.kind) == stack_card.map(|c| c.kind.next()).flatten() {
-                    true
-                } else {
+                if has_children || suit != target.suit {
                     false
-                }
-            },
-            StackKind::Stack => {
-                if stack_card.is_none() && target.kind == CardKind::King {
-                    true
-                } else if let Some(stack_card) = stack_card {
-                    target.can_stack(&stack_card)
                 } else {
-                    false
+                    self.rules.accepts(stack_card, target)
                 }
             },
+            StackKind::Stack => self.rules.accepts(stack_card, target),
         }
     }
 }
@@ -225,6 +418,9 @@ pub enum GameState {
     AutoSolving,
     Shuffle,
     Won,
+    Paused,
+    /// There are no moves left that get anywhere
+    GameOver,
 }
 
 #[derive(Debug)]
@@ -251,6 +447,131 @@ pub enum Action {
 #[derive(Default, Resource)]
 pub struct Actions(pub Vec<Action>);
 
+/// The undo system, which is scheduled once for each state it can run in
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemSet)]
+pub struct UndoSet;
+
+/// Every line played in this deal as moves of the model, kept when moves are undone and something else is played
+#[derive(Debug, Default, Resource)]
+pub struct UndoTree(pub MoveTree);
+
+/// Undo the last action, for anything other than the keyboard and mouse
+#[derive(Debug, Event)]
+pub struct UndoRequested;
+
+/// The entities on the table, read back into a `Board` so the rules model can be used on the game being played
+#[derive(SystemParam)]
+pub struct BoardEntities<'w, 's> {
+    deal: Option<Res<'w, Deal>>,
+    piles: Query<'w, 's, (Entity, &'static BoardPile)>,
+    decks: Query<'w, 's, &'static Deck>,
+    cards: Query<'w, 's, (&'static Card, &'static CardFace)>,
+    children: Query<'w, 's, &'static Children>,
+}
+
+impl BoardEntities<'_, '_> {
+    /// `None` before the first game has been dealt
+    pub fn board(&self) -> Option<Board> {
+        let mut board = self.deal.as_ref()?.board.clone();
+        if let Ok(deck) = self.decks.get_single() {
+            board.stock = deck.cards.clone();
+            board.recycles = deck.recycles;
+        }
+        for (entity, pile) in self.piles.iter() {
+            let mut cards = Vec::new();
+            let first = self.children.get(entity).ok().and_then(|children| children.first().copied());
+            walk_children(first, &self.children, &mut |e| cards.extend(self.cards.get(e).ok().map(|(card, face)| (*card, *face))));
+            match pile.0 {
+                Pile::Waste => board.waste = cards.into_iter().map(|(card, _)| card).collect(),
+                Pile::Reserve => board.reserve = cards,
+                Pile::Foundation(i) => board.foundations[i].cards = cards.into_iter().map(|(card, _)| card).collect(),
+                Pile::Tableau(i) => board.tableau[i].cards = cards,
+            }
+        }
+        Some(board)
+    }
+
+    /// The base entity of a pile
+    pub fn entity(&self, pile: Pile) -> Option<Entity> {
+        self.piles.iter().find(|(_, board_pile)| board_pile.0 == pile).map(|(entity, _)| entity)
+    }
+
+    /// The pile with this base entity
+    pub fn pile(&self, entity: Entity) -> Option<Pile> {
+        self.piles.get(entity).ok().map(|(_, pile)| pile.0)
+    }
+
+    /// The actions taken since the deal as moves of the model, stopping at the first one it won't allow
+    pub fn moves(&self, actions: &[Action]) -> Vec<Move> {
+        let mut board = if let Some(deal) = &self.deal {deal.board.clone()} else {return Vec::new()};
+        let mut moves = Vec::new();
+        for action in actions {
+            let mv = match action {
+                Action::Draw(_) => Move::Draw,
+                Action::ResetDeck => Move::Recycle,
+                Action::MoveCard {card, from, to, ..} => {
+                    let (from, to) = if let (Some(from), Some(to)) = (self.pile(*from), self.pile(*to)) {(from, to)} else {break};
+                    // The model fills from the reserve as part of the move that emptied the pile
+                    if from == Pile::Reserve && board.top(to) == Some(*card) {
+                        continue
+                    }
+                    let cards = board.cards(from);
+                    let count = if let Some(i) = cards.iter().rposition(|c| c == card) {cards.len() - i} else {break};
+                    Move::Cards {from, to, count}
+                },
+            };
+            if !board.moves().contains(&mv) {
+                warn!("{:?} doesn't match the rules, leaving it out of the record", mv);
+                break
+            }
+            board.apply(mv);
+            moves.push(mv);
+        }
+        moves
+    }
+}
+
+/// Write the game being played to a record in the data folder with ctrl+S
+pub fn save_record_system(
+    keys: Res<Input<KeyCode>>,
+    variant: Res<Variant>,
+    tree: Res<UndoTree>,
+    deal: Option<Res<Deal>>,
+) {
+    if !(keys.just_pressed(KeyCode::S) && keys.pressed(KeyCode::ControlLeft)) {
+        return
+    }
+    let deal = if let Some(deal) = deal {deal} else {return};
+    let record = GameRecord::from_tree(&variant.name, deal.seed, deal.open, &deal.board, &tree.0);
+    save_file("records", &variant.name, deal.seed, &record.to_string());
+}
+
+/// Write the layout the game being played was dealt from to the data folder with ctrl+E
+pub fn export_layout_system(
+    keys: Res<Input<KeyCode>>,
+    variant: Res<Variant>,
+    deal: Option<Res<Deal>>,
+) {
+    if !(keys.just_pressed(KeyCode::E) && keys.pressed(KeyCode::ControlLeft)) {
+        return
+    }
+    if let Some(deal) = deal {
+        save_file("layouts", &variant.name, deal.seed, &format_layout(&variant.name, &deal.board));
+    }
+}
+
+/// Save text to a new file in a folder of the data folder, named after the game
+fn save_file(folder: &str, variant: &str, seed: Option<u64>, text: &str) {
+    let dir = data_dir().join(folder);
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default();
+    let seed = seed.map(|seed| seed.to_string()).unwrap_or_else(|| "layout".to_string());
+    let path = dir.join(format!("{}-{}-{}.txt", variant.to_lowercase().replace(' ', "-"), seed, stamp));
+    match fs::create_dir_all(&dir).and_then(|_| fs::write(&path, text)) {
+        Ok(()) => info!("Saved to {}", path.display()),
+        Err(e) => warn!("Couldn't save to {}: {}", path.display(), e),
+    }
+}
+
 
 pub fn setup(
     mut commands: Commands,
@@ -354,125 +675,184 @@ pub fn setup(
                         ..Default::default()
                     });
                 });
+
+            parent.spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(150.0),
+                        height: Val::Px(20.0),
+                        margin: UiRect {
+                            left: Val::Px(10.0),
+                            ..Default::default()
+                        },
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        align_self: AlignSelf::FlexEnd,
+                        ..Default::default()
+                    },
+                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                    ..Default::default()
+                })
+                .insert(ResetButton::Restart)
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            "Restart This Deal",
+                            TextStyle {
+                                font: font_handle.clone(),
+                                font_size: 20.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                            }
+                        ),
+                        ..Default::default()
+                    });
+                });
+
+            parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font: font_handle.clone(),
+                            font_size: 20.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        }
+                    ),
+                    style: Style {
+                        margin: UiRect {
+                            left: Val::Px(20.0),
+                            ..Default::default()
+                        },
+                        align_self: AlignSelf::FlexEnd,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(HudText);
         });
 }
 
-pub fn clean_cards(mut commands: Commands, cleanup: Query<Entity, Or<(With<Card>, With<Stack>, With<Deck>, With<DiscardPile>)>>) {
+pub fn clean_cards(
+    mut commands: Commands,
+    cleanup: Query<Entity, Or<(With<Card>, With<Stack>, With<DiscardPile>, With<Reserve>)>>,
+    decks: Query<Entity, With<Deck>>,
+) {
     for entity in cleanup.iter() {
         commands.entity(entity).despawn();
     }
+    commands.remove_resource::<StuckCheck>();
+    commands.remove_resource::<Navigate>();
+    // The deck has its empty space and label as children
+    for entity in decks.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
 
 }
 
-pub fn reset_cards(
-    mut commands: Commands,
-    mut game_state: ResMut<NextState<GameState>>,
-    card_texture: Res<CardsTextureHandle>,
-    windows: Query<&Window>,
-    mut actions: ResMut<Actions>,
-    mut reset_menu: Query<&mut Style, With<ResetMenuRoot>>
-) {
-    actions.0.clear();
-    let mut rng = rand::thread_rng();
-    let mut deck = Vec::new();
-    for suit in [Suit::Spades, Suit::Clubs, Suit::Hearts, Suit::Diamonds] {
-        deck.push(Card {suit: suit, kind: CardKind::Ace});
-        for n in 2..=10 {
-            deck.push(Card {suit: suit, kind: CardKind::Number(n)});
+/// Pile positions on the table for the current window size
+struct Table {
+    spacing: f32,
+    half_width: f32,
+    half_height: f32,
+    /// Y of the row holding the deck, discard and completed piles
+    top_y: f32,
+    /// Y of the tableau row
+    stacks_y: f32,
+}
+
+impl Table {
+    fn new(window: &Window, spacing: f32) -> Self {
+        Self {
+            spacing,
+            half_width: window.width() / 2.0,
+            half_height: window.height() / 2.0,
+            top_y: (window.height() / 2.0) - 25.0 - (CARD_HEIGHT / 2.0),
+            stacks_y: window.height() * 0.1,
         }
-        deck.push(Card {suit: suit, kind: CardKind::Jack});
-        deck.push(Card {suit: suit, kind: CardKind::Queen});
-        deck.push(Card {suit: suit, kind: CardKind::King});
     }
 
-    deck.shuffle(&mut rng);
+    fn x(&self, slot: usize) -> f32 {
+        50.0 + (self.spacing * (slot as f32)) + (CARD_WIDTH / 2.0) - self.half_width
+    }
 
-    let window = if let Ok(w) = windows.get_single() {w} else {return};
-    let window_half_width = window.width() / 2.0;
-    let window_half_height = window.height() / 2.0;
-    let stacks_y = window.height() * 0.1;
+    /// Position of a pile in the top row
+    fn top(&self, slot: usize) -> Vec2 {
+        Vec2::new(self.x(slot), self.top_y)
+    }
 
-    let stack_x = |stack: usize| {
-        50.0 + (175.0 * (stack as f32)) + (CARD_WIDTH / 2.0) - window_half_width
-    };
+    /// Position of a pile in the tableau row
+    fn row(&self, slot: usize) -> Vec2 {
+        Vec2::new(self.x(slot), self.stacks_y)
+    }
+}
 
-    let mut stacks: Vec<Vec<Entity>> = (0..7).map(|stack| {
-        let pos = Vec2::new(stack_x(stack), stacks_y);
-        vec![
-            commands.spawn(SpriteSheetBundle {
-                    texture_atlas: card_texture.0.clone(),
-                    sprite: TextureAtlasSprite {
-                        index: EMPTY_SPACE,
-                        ..Default::default()
-                    },
-                    transform: Transform::from_xyz(pos.x, pos.y, 0.0),
-                    ..Default::default()
-                })
-                .insert(Stack::new(StackKind::Stack))
-                // Droppable area extending to the bottom of the screen
-                .insert(Droppable {zone: Area::new(pos.x - CARD_WIDTH / 2.0, -window_half_height, CARD_WIDTH, pos.y + window_half_height + CARD_HEIGHT)})
-                .id()
-        ]
-    }).collect();
-
-    for stack_index in 0..7 {
-        let next_card = deck.pop().unwrap();
-
-        let y = if stacks[stack_index].len() == 1 {
-            0.0
-        } else {
-            -CARD_STACK_SPACE
-        };
-        let new = commands.spawn(SpriteSheetBundle {
-                    texture_atlas: card_texture.0.clone(),
-                    transform: Transform::from_xyz(0.0, y, 1.0),
-                    ..Default::default()
-                })
-                .insert(next_card)
-                .insert(CardFace::Up)
-                .insert(Clickable::default())
-                .insert(Draggable)
-                .id();
-        commands.entity(*stacks[stack_index].last().unwrap()).add_child(new);
-        stacks[stack_index].push(new);
-
-        for next_stack in (stack_index + 1)..7 {
-            let card = deck.pop().unwrap();
-            let y = if stacks[next_stack].len() == 1 {
-                0.0
-            } else {
-                -CARD_STACK_SPACE
-            };
-            let new = commands.spawn(SpriteSheetBundle {
-                        texture_atlas: card_texture.0.clone(),
-                        transform: Transform::from_xyz(0.0, y, 1.0),
-                        ..Default::default()
-                    })
-                    .insert(card)
-                    .insert(CardFace::Down)
-                    .insert(Clickable::default())
-                    .id();
-            commands.entity(*stacks[next_stack].last().unwrap()).add_child(new);
-            stacks[next_stack].push(new);
-        }
+/// Spawn a card on top of `parent`. Face up cards can be dragged
+fn spawn_card(commands: &mut Commands, texture: &Handle<TextureAtlas>, parent: Entity, card: Card, face: CardFace, y: f32) -> Entity {
+    let mut new = commands.spawn(SpriteSheetBundle {
+            texture_atlas: texture.clone(),
+            transform: Transform::from_xyz(0.0, y, 1.0),
+            ..Default::default()
+        });
+    new.insert(card)
+        .insert(face)
+        .insert(Clickable::default());
+    if face == CardFace::Up {
+        new.insert(Draggable);
     }
+    let new = new.id();
+    commands.entity(parent).add_child(new);
+    new
+}
+
+fn spawn_tableau(commands: &mut Commands, texture: &Handle<TextureAtlas>, table: &Table, pos: Vec2, stack: Stack) -> Entity {
+    commands.spawn(SpriteSheetBundle {
+            texture_atlas: texture.clone(),
+            sprite: TextureAtlasSprite {
+                index: EMPTY_SPACE,
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(pos.x, pos.y, 0.0),
+            ..Default::default()
+        })
+        .insert(stack)
+        // Droppable area extending to the bottom of the screen
+        .insert(Droppable {zone: Area::new(pos.x - CARD_WIDTH / 2.0, -table.half_height, CARD_WIDTH, pos.y + table.half_height + CARD_HEIGHT)})
+        .id()
+}
+
+fn spawn_foundation(commands: &mut Commands, texture: &Handle<TextureAtlas>, pos: Vec2, stack: Stack) -> Entity {
+    let suit = match stack.kind {
+        StackKind::Ordered(suit) => suit,
+        StackKind::Stack => panic!("completed piles must be ordered"),
+    };
+    commands.spawn(SpriteSheetBundle {
+            texture_atlas: texture.clone(),
+            sprite: TextureAtlasSprite {
+                index: FINAL_STACKS + suit.row(),
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(pos.x, pos.y, 0.0),
+            ..Default::default()
+        })
+        .insert(stack)
+        .insert(Droppable {zone: Area::new(pos.x - CARD_WIDTH / 2.0, pos.y - CARD_HEIGHT / 2.0, CARD_WIDTH, CARD_HEIGHT)})
+        .id()
+}
 
-    let deck_pos = Vec2::new((CARD_WIDTH / 2.0) + 50.0 - window_half_width, window_half_height - 25.0 - (CARD_HEIGHT / 2.0));
+fn spawn_deck(commands: &mut Commands, texture: &Handle<TextureAtlas>, font: &Handle<Font>, pos: Vec2, cards: Vec<Card>) {
     commands.spawn(SpriteSheetBundle {
-            texture_atlas: card_texture.0.clone(),
-            transform: Transform::from_xyz((CARD_WIDTH / 2.0) + 50.0 - window_half_width, window_half_height - 25.0 - (CARD_HEIGHT / 2.0), 1.0),
+            texture_atlas: texture.clone(),
+            transform: Transform::from_xyz(pos.x, pos.y, 1.0),
             sprite: TextureAtlasSprite {
                 index: BACK_BLUE,
                 ..Default::default()
             },
             ..Default::default()
         })
-        .insert(Deck {cards: deck})
-        .insert(Clickable::at(deck_pos))
+        .insert(Deck {cards, recycles: 0})
+        .insert(Clickable::at(pos))
         .with_children(|parent| {
             // Empty space below the deck
             parent.spawn(SpriteSheetBundle {
-                texture_atlas: card_texture.0.clone(),
+                texture_atlas: texture.clone(),
                 transform: Transform::from_xyz(0.0, 0.0, -1.0),
                 sprite: TextureAtlasSprite {
                     index: EMPTY_SPACE,
@@ -480,34 +860,201 @@ pub fn reset_cards(
                 },
                 ..Default::default()
             });
+            parent.spawn(Text2dBundle {
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 20.0,
+                            color: Color::WHITE,
+                        }
+                    ).with_alignment(TextAlignment::Center),
+                    transform: Transform::from_xyz(0.0, -(CARD_HEIGHT / 2.0) - 15.0, 1.0),
+                    ..Default::default()
+                })
+                .insert(PassesText);
         });
+}
 
+fn spawn_discard(commands: &mut Commands, texture: &Handle<TextureAtlas>, pos: Vec2) -> Entity {
     commands.spawn(SpriteSheetBundle {
-            transform: Transform::from_xyz((CARD_WIDTH / 2.0) + 50.0 + 175.0 - window_half_width , window_half_height - 25.0 - (CARD_HEIGHT / 2.0), 1.0),
-            texture_atlas: card_texture.0.clone(),
+            transform: Transform::from_xyz(pos.x, pos.y, 1.0),
+            texture_atlas: texture.clone(),
             sprite: TextureAtlasSprite {
                 index: EMPTY_SPACE,
                 ..Default::default()
             },
             ..Default::default()
         })
-        .insert(DiscardPile::default());
+        .insert(DiscardPile::default())
+        .id()
+}
 
-    for suit in [Suit::Spades, Suit::Clubs, Suit::Hearts, Suit::Diamonds] {
-        let stack_x = (deck_pos.x + (175.0 * 3.0)) + (175.0 * suit.row() as f32);
-        let stack_pos = Vec2::new(stack_x, deck_pos.y);
-        commands.spawn(SpriteSheetBundle {
-            texture_atlas: card_texture.0.clone(),
+fn spawn_reserve(commands: &mut Commands, texture: &Handle<TextureAtlas>, pos: Vec2) -> Entity {
+    commands.spawn(SpriteSheetBundle {
+            texture_atlas: texture.clone(),
             sprite: TextureAtlasSprite {
-                index: FINAL_STACKS + suit.row(),
+                index: EMPTY_SPACE,
                 ..Default::default()
             },
-            transform: Transform::from_xyz(stack_pos.x, stack_pos.y, 0.0),
+            transform: Transform::from_xyz(pos.x, pos.y, 0.0),
             ..Default::default()
         })
-        .insert(Stack::new(StackKind::Ordered(suit)))
-        .insert(Droppable {zone: Area::new(stack_pos.x - CARD_WIDTH / 2.0 - window_half_width, stack_pos.y - CARD_HEIGHT / 2.0, CARD_WIDTH, CARD_HEIGHT)});
+        .insert(Reserve)
+        .id()
+}
+
+/// Start dealing a new game in the background. When winnable deals are turned on this keeps dealing
+/// until the solver finds a way to win, or gives up after `WINNABLE_SEARCH_LIMIT`
+pub fn shuffle_cards(
+    mut commands: Commands,
+    rules: GameRules,
+    open_deal: Res<OpenDeal>,
+    winnable: Res<WinnableDeals>,
+    penalty: Res<TakeBackPenalty>,
+    layout: Option<Res<ImportedLayout>>,
+    daily: Option<Res<DailyRequested>>,
+    restart: Option<Res<RestartRequested>>,
+    current: Option<Res<Deal>>,
+) {
+    let variant = rules.variant.clone();
+    let (draw, passes) = (rules.draw_num(), rules.pass_limit());
+    let (open, winnable, penalty) = (open_deal.0, winnable.0, penalty.0);
+    let daily = daily.map(|_| {
+        commands.remove_resource::<DailyRequested>();
+        Date::today()
+    });
+    // An imported layout is only used for the first game dealt
+    let imported = layout.and_then(|layout| {
+        commands.remove_resource::<ImportedLayout>();
+        parse_layout(&variant, draw, passes, &layout.0)
+            .map_err(|e| error!("Couldn't deal the layout: {}", e))
+            .ok()
+    });
+    let restart = restart.and_then(|_| {
+        commands.remove_resource::<RestartRequested>();
+        current.map(|deal| deal.clone())
+    });
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let stop = cancelled.clone();
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        if let Some(deal) = restart {
+            return deal
+        }
+        let scored = |board: Board| Board {take_back_penalty: penalty, ..board};
+        if let Some(board) = imported {
+            return Deal {seed: None, open: false, daily: None, board: scored(board), unverified: false}
+        }
+        if let Some(date) = daily {
+            let board = scored(Board::deal(&variant, draw, passes, open, shuffled_deck(variant.decks, date.seed())));
+            return Deal {seed: Some(date.seed()), open, daily, board, unverified: false}
+        }
+        let mut rng = StdRng::from_entropy();
+        let start = Instant::now();
+        loop {
+            let seed = rng.gen();
+            let board = scored(Board::deal(&variant, draw, passes, open, shuffled_deck(variant.decks, seed)));
+            if !winnable {
+                return Deal {seed: Some(seed), open, daily: None, board, unverified: false}
+            }
+            let left = WINNABLE_SEARCH_LIMIT.saturating_sub(start.elapsed());
+            if left.is_zero() || stop.load(Ordering::Relaxed) {
+                info!("No winnable deal found in {:?}, dealing seed {} unchecked", start.elapsed(), seed);
+                return Deal {seed: Some(seed), open, daily: None, board, unverified: true}
+            }
+            let report = solve(&board, SOLVE_BUDGET.min(left));
+            debug!("Deal {} searched {} positions in {:?}: {:?}", seed, report.nodes, report.elapsed, report.outcome);
+            if report.is_solved() {
+                return Deal {seed: Some(seed), open, daily: None, board, unverified: false}
+            }
+        }
+    });
+    commands.insert_resource(DealTask {task, cancelled});
+}
+
+/// Stop looking for a winnable deal and go back to the main menu with Escape
+pub fn cancel_dealing(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    task: Option<Res<DealTask>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    if !keys.just_pressed(KeyCode::Escape) {
+        return
+    }
+    if let Some(task) = task {
+        // Already dealt this frame
+        if task.task.is_finished() {
+            return
+        }
+        task.cancelled.store(true, Ordering::Relaxed);
+        commands.remove_resource::<DealTask>();
+    }
+    game_state.set(GameState::Menu);
+}
+
+/// Only counts time while the window has focus
+pub fn game_clock_system(time: Res<Time>, mut clock: ResMut<GameClock>, windows: Query<&Window>) {
+    if windows.iter().all(|window| window.focused) {
+        clock.0.tick(time.delta());
+    }
+}
+
+pub fn hud_system(
+    actions: Res<Actions>,
+    clock: Res<GameClock>,
+    board: BoardEntities,
+    mut stats: ResMut<GameStats>,
+    mut q_text: Query<&mut Text, With<HudText>>,
+) {
+    if actions.is_changed() {
+        if let (Some(deal), Some(current)) = (&board.deal, board.board()) {
+            let moves = board.moves(&actions.0);
+            *stats = GameStats {
+                moves: moves.len(),
+                score: deal.board.score(&moves),
+                passes_left: current.passes.map(|passes| passes.saturating_sub(current.recycles)),
+            };
+        }
+    }
+    for mut text in q_text.iter_mut() {
+        text.sections[0].value = stats.summary(&clock);
+    }
+}
+
+/// Lay out the deal once it is ready
+pub fn reset_cards(
+    mut commands: Commands,
+    mut game_state: ResMut<NextState<GameState>>,
+    card_texture: Res<CardsTextureHandle>,
+    font: Res<FontHandle>,
+    variant: Res<Variant>,
+    mut task: ResMut<DealTask>,
+    mut windows: Query<&mut Window>,
+    mut actions: ResMut<Actions>,
+    mut clock: ResMut<GameClock>,
+    mut reset_menu: Query<&mut Style, With<ResetMenuRoot>>
+) {
+    let deal = if let Some(d) = future::block_on(future::poll_once(&mut task.task)) {d} else {return};
+    commands.remove_resource::<DealTask>();
+    actions.0.clear();
+    clock.0.reset();
+
+    let mut window = if let Ok(w) = windows.get_single_mut() {w} else {return};
+    if window.width() != variant.table_width() {
+        let height = window.height();
+        window.resolution.set(variant.table_width(), height);
     }
+    let table = Table::new(&window, variant.pile_spacing);
+    match deal.seed {
+        Some(seed) => info!("Dealing seed {}", seed),
+        None => info!("Dealing an imported layout"),
+    }
+    spawn_board(&mut commands, &card_texture.0, &font.0, &table, &variant, &deal.board);
+    if deal.unverified {
+        spawn_notice(&mut commands, &font.0, "No winnable deal found in time, this one may not be");
+    }
+    commands.insert_resource(deal);
 
     for mut style in reset_menu.iter_mut() {
         style.display = Display::Flex;
@@ -516,13 +1063,165 @@ pub fn reset_cards(
     game_state.set(GameState::Playing);
 }
 
-pub fn deck_update_system(mut decks: Query<(&Deck, &mut Visibility), Changed<Deck>>) {
-    for (deck, mut visible) in decks.iter_mut() {
-        *visible = if deck.cards.len() > 0 {Visibility::Visible} else {Visibility::Hidden};
+/// Spawn the piles of the variant with the cards of the board on them
+fn spawn_board(commands: &mut Commands, texture: &Handle<TextureAtlas>, font: &Handle<Font>, table: &Table, variant: &Variant, board: &Board) {
+    let mut foundations = board.foundations.iter().enumerate();
+    let mut tableau = board.tableau.iter().enumerate();
+    for pile in variant.piles.iter() {
+        let pos = |i: usize| match pile.at {
+            Slot::Top(slot) => table.top(slot + i),
+            Slot::Row(slot) => table.row(slot + i),
+        };
+        match pile.kind {
+            PileKind::Stock => {
+                spawn_deck(commands, texture, font, pos(0), board.stock.clone());
+            },
+            PileKind::Waste => {
+                let discard = spawn_discard(commands, texture, pos(0));
+                commands.entity(discard).insert(BoardPile(Pile::Waste));
+            },
+            PileKind::Reserve => {
+                let reserve = spawn_reserve(commands, texture, pos(0));
+                commands.entity(reserve).insert(BoardPile(Pile::Reserve));
+                spawn_pile(commands, texture, reserve, &board.reserve, 0.0);
+            },
+            PileKind::Foundation => {
+                for (i, (n, foundation)) in foundations.by_ref().take(pile.count).enumerate() {
+                    let entity = spawn_foundation(commands, texture, pos(i), Stack::new(StackKind::Ordered(foundation.suit), foundation.rules));
+                    commands.entity(entity).insert(BoardPile(Pile::Foundation(n)));
+                    let cards: Vec<(Card, CardFace)> = foundation.cards.iter().map(|card| (*card, CardFace::Up)).collect();
+                    spawn_pile(commands, texture, entity, &cards, 0.0);
+                }
+            },
+            PileKind::Tableau => {
+                for (i, (n, stack)) in tableau.by_ref().take(pile.count).enumerate() {
+                    let entity = spawn_tableau(commands, texture, table, pos(i), Stack::new(StackKind::Stack, stack.rules));
+                    commands.entity(entity).insert(BoardPile(Pile::Tableau(n)));
+                    spawn_pile(commands, texture, entity, &stack.cards, CARD_STACK_SPACE);
+                }
+            },
+        }
+    }
+}
+
+/// Spawn a chain of cards from the bottom up, each offset by `spread` from the one below
+fn spawn_pile(commands: &mut Commands, texture: &Handle<TextureAtlas>, base: Entity, cards: &[(Card, CardFace)], spread: f32) {
+    let mut top = base;
+    for (i, (card, face)) in cards.iter().enumerate() {
+        let y = if i == 0 {0.0} else {-spread};
+        top = spawn_card(commands, texture, top, *card, *face, y);
+    }
+}
+
+pub fn deck_update_system(
+    deal: Res<Deal>,
+    mut decks: Query<(&Deck, &Children, &mut TextureAtlasSprite), Changed<Deck>>,
+    mut q_text: Query<&mut Text, With<PassesText>>,
+) {
+    let limit = deal.board.passes;
+    for (deck, children, mut sprite) in decks.iter_mut() {
+        // Includes the pass currently being made
+        let passes_left = limit.map(|limit| limit.saturating_sub(deck.recycles));
+        let exhausted = deck.cards.is_empty() && passes_left.map(|left| left <= 1).unwrap_or(false);
+        if let Some(top) = deck.cards.last().filter(|_| deal.open) {
+            sprite.index = top.texture_index();
+            sprite.color = Color::WHITE;
+        } else if !deck.cards.is_empty() {
+            sprite.index = BACK_BLUE;
+            sprite.color = Color::WHITE;
+        } else if exhausted {
+            sprite.index = EMPTY_SPACE;
+            sprite.color = NO_REDEALS_COLOR;
+        } else {
+            sprite.index = EMPTY_SPACE;
+            sprite.color = Color::WHITE;
+        }
+        for child in children.iter() {
+            if let Ok(mut text) = q_text.get_mut(*child) {
+                text.sections[0].value = match passes_left {
+                    None => String::new(),
+                    Some(_) if exhausted => "No more redeals".to_string(),
+                    Some(1) => "Last pass".to_string(),
+                    Some(left) => format!("{} passes left", left),
+                };
+            }
+        }
+    }
+}
+
+/// Show every card left in the deck, in the order they will be drawn, while the deck is hovered in an open deal
+pub fn stock_preview_system(
+    mut commands: Commands,
+    deal: Res<Deal>,
+    card_texture: Res<CardsTextureHandle>,
+    windows: Query<&Window>,
+    q_deck: Query<(Ref<Deck>, Option<&MouseInteraction>)>,
+    q_preview: Query<Entity, With<StockPreview>>,
+) {
+    let (deck, interaction) = if let Ok(d) = q_deck.get_single() {d} else {return};
+    let hovered = deal.open && interaction.is_some() && !deck.cards.is_empty();
+    let shown = !q_preview.is_empty();
+    if hovered == shown && !(shown && deck.is_changed()) {
+        return
+    }
+    for entity in q_preview.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !hovered {
+        return
+    }
+
+    let window = if let Ok(w) = windows.get_single() {w} else {return};
+    // Cards overlap so only their corners show, with a gap after each draw
+    let overlap = 40.0;
+    let group_gap = 20.0;
+    let row_space = CARD_STACK_SPACE + 10.0;
+    let draw_num = deal.board.draw.max(1);
+    let group_width = (draw_num as f32 * overlap) + group_gap;
+    // Keep whole draws on the same row
+    let groups_per_row = (((window.width() - 100.0 - CARD_WIDTH) / group_width) as usize).max(1);
+    let per_row = groups_per_row * draw_num;
+    let rows = deck.cards.len().div_ceil(per_row);
+    let height = CARD_HEIGHT + ((rows - 1) as f32 * row_space) + 20.0;
+    commands.spawn(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0.0, 0.0, 0.0, 0.6),
+                custom_size: Some(Vec2::new(window.width(), height)),
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, 400.0),
+            ..Default::default()
+        })
+        .insert(StockPreview)
+        .with_children(|parent| {
+            let left = -(window.width() / 2.0) + 50.0 + (CARD_WIDTH / 2.0);
+            let top = (height / 2.0) - 10.0 - (CARD_HEIGHT / 2.0);
+            // The last card in the deck is drawn first
+            for (i, card) in deck.cards.iter().rev().enumerate() {
+                let (row, column) = (i / per_row, i % per_row);
+                let x = left + (column as f32 * overlap) + ((column / draw_num) as f32 * group_gap);
+                let y = top - (row as f32 * row_space);
+                parent.spawn(SpriteSheetBundle {
+                    texture_atlas: card_texture.0.clone(),
+                    sprite: TextureAtlasSprite {
+                        index: card.texture_index(),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(x, y, 1.0 + (i as f32 * 0.01)),
+                    ..Default::default()
+                });
+            }
+        });
+}
+
+pub fn hide_stock_preview(mut commands: Commands, q_preview: Query<Entity, With<StockPreview>>) {
+    for entity in q_preview.iter() {
+        commands.entity(entity).despawn_recursive();
     }
 }
 
 pub fn discard_update_system(
+    variant: Res<Variant>,
     q_discard: Query<Entity, With<DiscardPile>>,
     q_children: Query<&Children>,
     mut q_transform: Query<&mut Transform>,
@@ -537,7 +1236,7 @@ pub fn discard_update_system(
         if q_interaction.get(*child).ok().map(|interaction| interaction.is_dragging()).unwrap_or(false) {
             continue
         }
-        if i < 2 && Some(*child) != first_child {
+        if i < variant.discard_fan && Some(*child) != first_child {
             // When we undo a draw, some entities at the top are no longer guaranteed to be there
             // TODO: Fix this system order or somehow otherwise ensure there are not stale entities
             if let Ok(mut transform) = q_transform.get_mut(*child) {
@@ -555,30 +1254,45 @@ pub fn win_check_system(
     mut commands: Commands,
     mut game_state: ResMut<NextState<GameState>>,
     actions: Res<Actions>,
+    mut blocked: ResMut<AutoSolveBlocked>,
     q_stacks: Query<(Entity, &Stack)>,
     q_card: Query<(&Card, &CardFace)>,
     q_children: Query<&Children>,
     q_deck: Query<&Deck>,
     q_discard: Query<Entity, With<DiscardPile>>,
+    q_reserve: Query<Entity, With<Reserve>>,
 ) {
+    match blocked.0 {
+        Some(len) if actions.0.len() < len => blocked.0 = Some(actions.0.len()),
+        Some(len) if actions.0.len() > len => blocked.0 = None,
+        _ => {},
+    }
+    let mut foundations = 0;
     let mut completed = 0;
-    for (stack_entity, _) in q_stacks.iter().filter(|(_entity, stack)| match stack.kind {StackKind::Ordered(_) => true, _ => false}) {
+    for (stack_entity, stack) in q_stacks.iter().filter(|(_entity, stack)| match stack.kind {StackKind::Ordered(_) => true, _ => false}) {
+        foundations += 1;
         let top = top_entity(stack_entity, &q_children);
         if let Ok((card, _)) = q_card.get(top) {
-            if card.kind == CardKind::King {
+            if card.kind == stack.last_rank() {
                 completed += 1;
             }
         }
     }
-    if completed == 4 {
+    if completed == foundations {
         info!("Game Won in {} moves!", actions.0.len());
         game_state.set(GameState::Won);
-    } else {
-        // The only place facedown cards will exist is on the board
-        if q_deck.single().cards.len() == 0 && q_card.iter().all(|(_, face)| face == &CardFace::Up) {
+    } else if blocked.0 != Some(actions.0.len()) {
+        // The only place facedown cards will exist is on the board, but in an open deal they don't have to be in order
+        let stacks_in_order = q_stacks.iter().filter(|(_, stack)| stack.kind == StackKind::Stack).all(|(stack_entity, stack)| {
+            let mut cards = Vec::new();
+            walk_children(Some(stack_entity), &q_children, &mut |e| cards.extend(q_card.get(e).ok().map(|(card, _)| *card)));
+            stack.rules.is_run(&cards)
+        });
+        if q_deck.single().cards.len() == 0 && q_card.iter().all(|(_, face)| face == &CardFace::Up) && stacks_in_order {
             let discard = q_discard.single();
             let top_discard = top_entity(discard, &q_children);
-            if discard == top_discard {
+            let reserve_empty = q_reserve.iter().all(|reserve| top_entity(reserve, &q_children) == reserve);
+            if discard == top_discard && reserve_empty {
                 info!("Attempting to auto-solve");
                 commands.insert_resource(SolveTimer(Timer::from_seconds(0.15, TimerMode::Repeating)));
                 game_state.set(GameState::AutoSolving);
@@ -592,8 +1306,9 @@ pub fn auto_solver(
     mut game_state: ResMut<NextState<GameState>>,
     mut solve_timer: ResMut<SolveTimer>,
     mut actions: ResMut<Actions>,
+    mut blocked: ResMut<AutoSolveBlocked>,
     time: Res<Time>,
-    q_stacks: Query<(Entity, &Stack)>,
+    board: BoardEntities,
     q_card: Query<&Card>,
     q_card_face: Query<&CardFace>,
     q_children: Query<&Children>,
@@ -601,49 +1316,164 @@ pub fn auto_solver(
     q_gtransform: Query<&GlobalTransform>,
     mut q_transform: Query<&mut Transform>,
 ) {
-    if !solve_timer.0.tick(time.delta()).finished() {
+    // Stopped this frame
+    if !solve_timer.0.tick(time.delta()).finished() || blocked.0 == Some(actions.0.len()) {
         return
     }
-    let mut stacks = HashMap::new();
-    let mut to_solve = Vec::new();
-    for (stack_entity, stack) in q_stacks.iter() {
-        let top = top_entity(stack_entity, &q_children);
-        match stack.kind {
-            StackKind::Ordered(suit) => {
-                let card = q_card.get(top).ok();
-                assert!(stacks.insert(suit, (stack_entity, top, card)).is_none());
-            },
-            StackKind::Stack => {
-                if top != stack_entity {
-                    let card = q_card.get(top).unwrap();
-                    to_solve.push((top, card))
-                }
-            }
-        }
-    }
-    if to_solve.len() == 0 {
+    let current = if let Some(b) = board.board() {b} else {return};
+    if current.is_won() {
         // Just go back to playing and let the normal check logic set it for now to double check that is working
         // This is will get stuck in a loop if its wrong, but it worked from the very beginning so...
         game_state.set(GameState::Playing);
+        return
     }
-    // Always solve them lowest to highest
-    to_solve.sort_by_key(|(_, card)| card.kind.column());
-
-    for (candidate, card) in to_solve {
-        if let Some((stack_entity, top, maybe_top_card)) = stacks.get(&card.suit) {
-            let stack = Stack::new(StackKind::Ordered(card.suit));
-            if stack.can_stack(*maybe_top_card, *card, false) {
-                actions.0.push(Action::MoveCard {
-                    card: card.clone(),
-                    from: bottom_entity(candidate, &q_parent),
-                    to: *stack_entity,
-                    y_offset: CARD_STACK_SPACE,
-                    parent_face_down: false,
-                });
-                move_card(&mut commands, &q_parent, &q_gtransform, &mut q_transform, &q_card, &q_card_face, candidate, *top, 0.0, 100);
-                break
-            }
+
+    // Everything is face up so the greedy agent always plays the lowest card it can onto a foundation
+    match GreedyAgent.choose(&Observation::new(&current)) {
+        Some(Move::Cards {from, to: to @ Pile::Foundation(_), count: 1}) => {
+            let (from, to) = (board.entity(from).unwrap(), board.entity(to).unwrap());
+            play_top_card(&mut commands, &mut actions, from, to, &q_card, &q_card_face, &q_children, &q_parent, &q_gtransform, &mut q_transform);
+        },
+        _ => {
+            // Wrapping ranks can leave a card buried under the one it has to follow, so hand control back to the player
+            info!("Auto-solve is stuck");
+            blocked.0 = Some(actions.0.len());
+            game_state.set(GameState::Playing);
+        },
+    }
+}
+
+/// Hand the game back to the player when the auto-solver is stopped with Escape, or when a move is undone while it
+/// runs or from the win screen. Runs while playing too, to know how many actions there were going in
+pub fn resume_play_system(
+    keys: Res<Input<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    actions: Res<Actions>,
+    mut blocked: ResMut<AutoSolveBlocked>,
+    mut last_len: Local<usize>,
+) {
+    let undone = actions.0.len() < *last_len;
+    *last_len = actions.0.len();
+    let stopped = keys.just_pressed(KeyCode::Escape);
+    let resumable = match state.get() {
+        GameState::AutoSolving => undone || stopped,
+        GameState::Won => undone,
+        _ => false,
+    };
+    if resumable {
+        blocked.0 = Some(actions.0.len());
+        game_state.set(GameState::Playing);
+    }
+}
+
+/// Move the top card of one pile onto another, recording it so it can be undone
+fn play_top_card(
+    commands: &mut Commands,
+    actions: &mut Actions,
+    from: Entity,
+    to: Entity,
+    q_card: &Query<&Card>,
+    q_card_face: &Query<&CardFace>,
+    q_children: &Query<&Children>,
+    q_parent: &Query<&Parent>,
+    q_gtransform: &Query<&GlobalTransform>,
+    q_transform: &mut Query<&mut Transform>,
+) {
+    let card = top_entity(from, q_children);
+    let top = top_entity(to, q_children);
+    actions.0.push(Action::MoveCard {
+        card: *q_card.get(card).unwrap(),
+        from,
+        to,
+        y_offset: q_transform.get(card).map(|t| t.translation.y).unwrap_or(CARD_STACK_SPACE),
+        parent_face_down: q_parent.get(card).map(|p| q_card_face.get(p.get()).ok() == Some(&CardFace::Down)).unwrap_or(false),
+    });
+    move_card(commands, q_parent, q_gtransform, q_transform, q_card, q_card_face, card, top, 0.0, 100);
+}
+
+/// Play cards onto the foundations after each move, one card a frame so each one animates
+pub fn auto_play_system(
+    mut commands: Commands,
+    mode: Res<AutoPlay>,
+    navigate: Option<Res<Navigate>>,
+    mut last_len: Local<usize>,
+    mut actions: ResMut<Actions>,
+    board: BoardEntities,
+    q_interaction: Query<&MouseInteraction>,
+    q_card: Query<&Card>,
+    q_card_face: Query<&CardFace>,
+    q_children: Query<&Children>,
+    q_parent: Query<&Parent>,
+    q_gtransform: Query<&GlobalTransform>,
+    mut q_transform: Query<&mut Transform>,
+) {
+    // Only after a new move so undoing a card off a foundation doesn't put it straight back
+    let grew = actions.0.len() > *last_len;
+    *last_len = actions.0.len();
+    // Going down another line plays exactly its moves
+    if !grew || *mode == AutoPlay::Off || navigate.is_some() || q_interaction.iter().any(|interaction| interaction.is_dragging()) {
+        return
+    }
+    // Nor straight back after it was taken off one
+    if let Some(Action::MoveCard {from, ..}) = actions.0.last() {
+        if let Some(Pile::Foundation(_)) = board.pile(*from) {
+            return
+        }
+    }
+    let current = if let Some(b) = board.board() {b} else {return};
+    let mv = match *mode {
+        AutoPlay::Off => None,
+        AutoPlay::Safe => current.safe_foundation_move(),
+        AutoPlay::Aggressive => current.moves().into_iter().find(|mv| matches!(mv, Move::Cards {to: Pile::Foundation(_), count: 1, ..})),
+    };
+    if let Some(Move::Cards {from, to, ..}) = mv {
+        let (from, to) = (board.entity(from).unwrap(), board.entity(to).unwrap());
+        play_top_card(&mut commands, &mut actions, from, to, &q_card, &q_card_face, &q_children, &q_parent, &q_gtransform, &mut q_transform);
+    }
+}
+
+/// Fill empty tableau stacks from the top of the reserve
+pub fn reserve_fill_system(
+    mut commands: Commands,
+    mut last_len: Local<usize>,
+    mut actions: ResMut<Actions>,
+    variant: Res<Variant>,
+    q_reserve: Query<Entity, With<Reserve>>,
+    q_stacks: Query<(Entity, &Stack)>,
+    q_card: Query<&Card>,
+    q_card_face: Query<&CardFace>,
+    q_children: Query<&Children>,
+    q_parent: Query<&Parent>,
+    q_gtransform: Query<&GlobalTransform>,
+    mut q_transform: Query<&mut Transform>,
+) {
+    if !variant.auto_fill {
+        return
+    }
+    // Only fill after a new move so undoing a fill doesn't immediately fill it again
+    let grew = actions.0.len() > *last_len;
+    *last_len = actions.0.len();
+    if !grew {
+        return
+    }
+    let reserve = if let Ok(r) = q_reserve.get_single() {r} else {return};
+    let mut top = top_entity(reserve, &q_children);
+    for (stack_entity, _) in q_stacks.iter().filter(|(entity, stack)| stack.kind == StackKind::Stack && top_entity(*entity, &q_children) == *entity) {
+        if top == reserve {
+            break
         }
+        let card = q_card.get(top).unwrap();
+        let parent = q_parent.get(top).unwrap().get();
+        actions.0.push(Action::MoveCard {
+            card: *card,
+            from: reserve,
+            to: stack_entity,
+            y_offset: 0.0,
+            parent_face_down: parent != reserve,
+        });
+        move_card(&mut commands, &q_parent, &q_gtransform, &mut q_transform, &q_card, &q_card_face, top, stack_entity, 0.0, 100);
+        top = parent;
     }
 }
 
@@ -719,6 +1549,7 @@ pub fn undo(
     mut q_deck: Query<&mut Deck>,
     keys: Res<Input<KeyCode>>,
     mouse: Res<Input<MouseButton>>,
+    mut ev_undo: EventReader<UndoRequested>,
     q_interaction: Query<&MouseInteraction>,
     q_card: Query<&Card>,
     q_card_face: Query<&CardFace>,
@@ -728,13 +1559,14 @@ pub fn undo(
     q_gtransform: Query<&GlobalTransform>,
     mut q_transform: Query<&mut Transform>,
 ) {
+    let requested = ev_undo.iter().count() > 0;
     // If we are currently dragging a card, don't attempt to undo anything
     if q_interaction.iter().any(|interaction| interaction.is_dragging()) {
         return
     }
 
     // ctrl+Z or right click to undo
-    if actions.0.len() > 0 && (keys.just_pressed(KeyCode::Z) && keys.pressed(KeyCode::ControlLeft)) || mouse.just_released(MouseButton::Right) {
+    if actions.0.len() > 0 && (keys.just_pressed(KeyCode::Z) && keys.pressed(KeyCode::ControlLeft)) || mouse.just_released(MouseButton::Right) || requested {
         if let Some(action) = actions.0.pop() {
             debug!("undo {:?}", action);
             let mut deck = q_deck.single_mut();
@@ -766,6 +1598,7 @@ pub fn undo(
                     }
                 },
                 Action::ResetDeck => {
+                    deck.recycles = deck.recycles.saturating_sub(1);
                     let mut discard_top = top_entity(discard_pile, &q_children);
                     while let Some(card) = deck.cards.pop() {
 
@@ -0,0 +1,202 @@
+//! Noticing when a game can't get anywhere any more, and what to do about it
+
+use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use bevy_easings::EasingComponent;
+use futures_lite::future;
+
+use crate::game::{Actions, BoardEntities, Deal, FontHandle, GameState, RestartRequested, UndoRequested};
+use crate::history::Navigate;
+use crate::mouse_input::MouseInteraction;
+use crate::pause::{spawn_button, spawn_text};
+use crate::stats::Statistics;
+use crate::variant::Variant;
+
+/// Positions looked at before assuming there's still something to be done
+pub const STUCK_SEARCH_LIMIT: usize = 5000;
+
+/// Whether the game is stuck, worked out away from the main thread since it can take a while
+#[derive(Resource)]
+pub struct StuckCheck {
+    task: Task<bool>,
+    /// `Board::key` of the position checked, so the answer is thrown away if the table has changed since
+    position: u64,
+}
+
+#[derive(Component)]
+pub struct GameOverRoot;
+
+#[derive(Component)]
+pub enum GameOverButton {
+    Undo,
+    Restart,
+    NewGame,
+}
+
+/// Check whether the game is stuck each time the position on the table changes, starting with the deal itself,
+/// once the cards have stopped moving
+pub fn stuck_system(
+    mut commands: Commands,
+    mut checked: Local<Option<u64>>,
+    mut pending: Local<bool>,
+    deal: Option<Res<Deal>>,
+    actions: Res<Actions>,
+    board: BoardEntities,
+    navigate: Option<Res<Navigate>>,
+    check: Option<ResMut<StuckCheck>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    q_easing: Query<(), With<EasingComponent<Transform>>>,
+    q_interaction: Query<&MouseInteraction>,
+) {
+    if deal.is_some_and(|deal| deal.is_changed()) {
+        // Restarting a deal gets it checked again too
+        *checked = None;
+        *pending = true;
+    }
+    if actions.is_changed() {
+        *pending = true;
+    }
+    if let Some(mut check) = check {
+        if let Some(stuck) = future::block_on(future::poll_once(&mut check.task)) {
+            commands.remove_resource::<StuckCheck>();
+            // Otherwise the table has moved on and the new position is checked next
+            if stuck && board.board().map(|current| current.key()) == Some(check.position) {
+                game_state.set(GameState::GameOver);
+            }
+        }
+        return
+    }
+    if !*pending || actions.is_changed() || navigate.is_some() || !q_easing.is_empty() || q_interaction.iter().any(|interaction| interaction.is_dragging()) {
+        return
+    }
+    let current = if let Some(b) = board.board() {b} else {return};
+    *pending = false;
+    let position = current.key();
+    if *checked == Some(position) {
+        return
+    }
+    *checked = Some(position);
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        current.is_stuck(STUCK_SEARCH_LIMIT)
+    });
+    commands.insert_resource(StuckCheck {task, position});
+}
+
+pub fn spawn_game_over(mut commands: Commands, font: Res<FontHandle>) {
+    let font = &font.0;
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            z_index: ZIndex::Global(10),
+            ..Default::default()
+        })
+        .insert(GameOverRoot)
+        .with_children(|parent| {
+            spawn_text(parent, font, "No Moves Left", 60.0);
+            spawn_text(parent, font, "Nothing more can go on the foundations or be turned over", 24.0);
+            spawn_button(parent, font, "Undo", GameOverButton::Undo);
+            spawn_button(parent, font, "Restart Same Deal", GameOverButton::Restart);
+            spawn_button(parent, font, "New Game", GameOverButton::NewGame);
+        });
+}
+
+pub fn despawn_game_over(mut commands: Commands, q_root: Query<Entity, With<GameOverRoot>>) {
+    for entity in q_root.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Undo goes back to the game. Dealing again or starting a new game counts this one as lost
+pub fn game_over_menu(
+    mut commands: Commands,
+    mut game_state: ResMut<NextState<GameState>>,
+    deal: Option<Res<Deal>>,
+    variant: Res<Variant>,
+    mut stats: ResMut<Statistics>,
+    mut ev_undo: EventWriter<UndoRequested>,
+    q_button: Query<(&GameOverButton, &Interaction), Changed<Interaction>>,
+) {
+    for (button, interaction) in q_button.iter() {
+        if *interaction != Interaction::Pressed {
+            continue
+        }
+        if let (GameOverButton::Restart | GameOverButton::NewGame, Some(deal)) = (button, &deal) {
+            stats.lose(&variant.name, deal.board.draw);
+        }
+        match button {
+            GameOverButton::Undo => {
+                ev_undo.send(UndoRequested);
+                game_state.set(GameState::Playing);
+            },
+            GameOverButton::Restart => {
+                commands.insert_resource(RestartRequested);
+                game_state.set(GameState::Shuffle);
+            },
+            GameOverButton::NewGame => game_state.set(GameState::Menu),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+    use crate::board::fixtures::{card, empty_board};
+    use crate::game::CardFace;
+
+    fn app(board: crate::board::Board) -> App {
+        let mut app = App::new();
+        app.add_plugins(TaskPoolPlugin::default())
+            .add_state::<GameState>()
+            .init_resource::<Actions>()
+            .insert_resource(Deal {seed: None, open: false, daily: None, board, unverified: false})
+            .add_systems(Update, stuck_system);
+        app
+    }
+
+    /// Update until a check of the position has been started and finished, returning the state the game is left in
+    fn settle(app: &mut App) -> GameState {
+        let mut started = false;
+        for _ in 0..1000 {
+            app.update();
+            if app.world.contains_resource::<StuckCheck>() {
+                started = true;
+            } else if started {
+                break
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(started);
+        // Going to the next state takes another frame
+        app.update();
+        *app.world.resource::<State<GameState>>().get()
+    }
+
+    #[test]
+    fn deal_stuck_from_the_start() {
+        let mut board = empty_board();
+        board.tableau[0].cards = vec![(card("5d"), CardFace::Down), (card("5s"), CardFace::Up)];
+        board.tableau[1].cards = vec![(card("6c"), CardFace::Up)];
+        let mut stuck = app(board.clone());
+        assert_eq!(settle(&mut stuck), GameState::GameOver);
+
+        // The same deal again is checked again
+        stuck.world.resource_mut::<NextState<GameState>>().set(GameState::Playing);
+        stuck.update();
+        let deal = stuck.world.resource::<Deal>().clone();
+        stuck.insert_resource(deal);
+        assert_eq!(settle(&mut stuck), GameState::GameOver);
+
+        board.tableau[1].cards = vec![(card("6h"), CardFace::Up)];
+        assert_eq!(settle(&mut app(board)), GameState::Menu);
+    }
+}
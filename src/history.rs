@@ -0,0 +1,332 @@
+//! Panel listing the moves of the line being played, including those undone from it. Clicking one goes back or
+//! forward to just after it, and lines that were undone and replaced can be switched back to
+
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+use bevy_easings::EasingComponent;
+
+use crate::board::{Board, Move, Pile};
+use crate::game::{Actions, BoardEntities, Card, CardKind, Deal, Deck, FontHandle, Suit, UndoRequested, UndoTree};
+use crate::mouse_input::{Dropped, MouseInteraction, Released, send_move};
+use crate::tree::MoveTree;
+
+/// Entries shown at once, the mouse wheel scrolls through the rest
+const VISIBLE_ENTRIES: usize = 20;
+
+#[derive(Debug, Default, Resource)]
+pub struct HistoryPanel {
+    pub open: bool,
+    /// Number of the newest entries scrolled out of view
+    pub scroll: usize,
+}
+
+/// Undo and play moves one at a time until the table is at the end of this line
+#[derive(Debug, Resource)]
+pub struct Navigate(pub Vec<Move>);
+
+#[derive(Component)]
+pub struct HistoryRoot;
+
+#[derive(Component)]
+pub struct HistoryToggle;
+
+/// Node of the undo tree to go to
+#[derive(Component)]
+pub struct HistoryEntry(pub usize);
+
+/// Switches to the next line played from the same position
+#[derive(Component)]
+pub struct BranchButton(pub usize);
+
+fn card_name(card: Card) -> String {
+    let rank = match card.kind {
+        CardKind::Ace => "A".to_string(),
+        CardKind::Number(n) => n.to_string(),
+        CardKind::Jack => "J".to_string(),
+        CardKind::Queen => "Q".to_string(),
+        CardKind::King => "K".to_string(),
+    };
+    let suit = match card.suit {
+        Suit::Spades => "Spades",
+        Suit::Clubs => "Clubs",
+        Suit::Diamonds => "Diamonds",
+        Suit::Hearts => "Hearts",
+    };
+    format!("{} of {}", rank, suit)
+}
+
+fn pile_name(pile: Pile) -> String {
+    match pile {
+        Pile::Waste => "waste".to_string(),
+        Pile::Reserve => "reserve".to_string(),
+        Pile::Foundation(i) => format!("foundation {}", i + 1),
+        Pile::Tableau(i) => format!("column {}", i + 1),
+    }
+}
+
+/// A move from a position as a line of the history, such as `7 of Hearts waste → column 3`
+pub fn describe(board: &Board, mv: Move) -> String {
+    match mv {
+        Move::Cards {from, to, count} => {
+            let cards = board.cards(from);
+            format!("{} {} → {}", card_name(cards[cards.len() - count]), pile_name(from), pile_name(to))
+        },
+        Move::Draw => format!("Draw {}", board.draw.min(board.stock.len())),
+        Move::Recycle => "Recycle stock".to_string(),
+    }
+}
+
+/// A row of the panel, with a button on the end to switch branches when it has one
+fn spawn_line(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    text: &str,
+    color: Color,
+    text_color: Color,
+    button: Option<impl Component>,
+    branch: Option<(String, BranchButton)>,
+) {
+    let mut line = parent.spawn(ButtonBundle {
+        style: Style {
+            width: Val::Px(340.0),
+            height: Val::Px(24.0),
+            padding: UiRect::horizontal(Val::Px(6.0)),
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        background_color: color.into(),
+        ..Default::default()
+    });
+    line.with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            text,
+            TextStyle {
+                font: font.clone(),
+                font_size: 18.0,
+                color: text_color,
+            },
+        ));
+        if let Some((label, branch)) = branch {
+            parent.spawn(ButtonBundle {
+                    style: Style {
+                        height: Val::Px(18.0),
+                        margin: UiRect::left(Val::Auto),
+                        padding: UiRect::horizontal(Val::Px(4.0)),
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: Color::rgb(0.3, 0.3, 0.3).into(),
+                    ..Default::default()
+                })
+                .insert(branch)
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        label,
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 14.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    ));
+                });
+        }
+    });
+    if let Some(button) = button {
+        line.insert(button);
+    }
+}
+
+/// Keep the undo tree following the moves on the table, starting it again for each deal
+pub fn tree_system(
+    deal: Option<Res<Deal>>,
+    actions: Res<Actions>,
+    board: BoardEntities,
+    mut tree: ResMut<UndoTree>,
+) {
+    let new_deal = deal.is_some_and(|deal| deal.is_changed());
+    if new_deal {
+        tree.0 = MoveTree::default();
+    }
+    if new_deal || actions.is_changed() {
+        tree.0.follow(&board.moves(&actions.0));
+    }
+}
+
+/// Open and close the panel with H or its button, scroll it with the mouse wheel and go to a clicked entry or branch
+pub fn history_system(
+    mut commands: Commands,
+    font: Res<FontHandle>,
+    keys: Res<Input<KeyCode>>,
+    undo_tree: Res<UndoTree>,
+    deal: Option<Res<Deal>>,
+    mut panel: ResMut<HistoryPanel>,
+    mut ev_wheel: EventReader<MouseWheel>,
+    q_root: Query<Entity, With<HistoryRoot>>,
+    q_toggle: Query<&Interaction, (Changed<Interaction>, With<HistoryToggle>)>,
+    q_entry: Query<(&HistoryEntry, &Interaction), Changed<Interaction>>,
+    q_branch: Query<(&BranchButton, &Interaction), Changed<Interaction>>,
+) {
+    let tree = &undo_tree.0;
+    if keys.just_pressed(KeyCode::H) || q_toggle.iter().any(|interaction| *interaction == Interaction::Pressed) {
+        panel.open = !panel.open;
+    }
+    let picked = q_entry.iter().map(|(entry, interaction)| (entry.0, interaction))
+        .chain(q_branch.iter().map(|(branch, interaction)| (branch.0, interaction)))
+        .find(|(_, interaction)| **interaction == Interaction::Pressed);
+    if let Some((node, _)) = picked {
+        if node != tree.current() {
+            commands.insert_resource(Navigate(tree.line(node)));
+        }
+    }
+
+    // From the deal to the position on the table, then on down the moves last undone from it
+    let mut nodes = vec![tree.current()];
+    while let Some(parent) = tree.parent(nodes[0]) {
+        nodes.insert(0, parent);
+    }
+    let current = nodes.len() - 1;
+    nodes.extend(tree.continuation(tree.current()).into_iter().skip(1));
+    let entries = nodes.len();
+    let scrolled = ev_wheel.iter().map(|wheel| wheel.y.signum() as isize).sum::<isize>();
+    if panel.open && scrolled != 0 {
+        let max = entries.saturating_sub(VISIBLE_ENTRIES) as isize;
+        panel.scroll = (panel.scroll as isize + scrolled).clamp(0, max) as usize;
+    }
+    // Bring the position on the table back into view
+    if undo_tree.is_changed() {
+        let scroll = entries.saturating_sub(current + 1 + VISIBLE_ENTRIES / 2);
+        if panel.scroll != scroll {
+            panel.scroll = scroll;
+        }
+    }
+    if !panel.is_changed() && !undo_tree.is_changed() && !q_root.is_empty() {
+        return
+    }
+    for entity in q_root.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let font = &font.0;
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(5.0),
+                bottom: Val::Px(5.0),
+                // Newest at the bottom, just above the button
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexEnd,
+                ..Default::default()
+            },
+            z_index: ZIndex::Global(5),
+            ..Default::default()
+        })
+        .insert(HistoryRoot)
+        .with_children(|parent| {
+            parent.spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(150.0),
+                        height: Val::Px(20.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                    ..Default::default()
+                })
+                .insert(HistoryToggle)
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        if panel.open {"Hide History"} else {"History"},
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 20.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    ));
+                });
+            if !panel.open {
+                return
+            }
+            let background = Color::rgba(0.1, 0.1, 0.1, 0.85);
+            let text_color = Color::rgb(0.9, 0.9, 0.9);
+            if panel.scroll > 0 {
+                spawn_line(parent, font, &format!("{} later", panel.scroll), background, text_color, None::<HistoryEntry>, None);
+            }
+            // Describing a move needs the position it was played from
+            let mut texts = vec!["Deal".to_string()];
+            if let Some(deal) = &deal {
+                let mut board = deal.board.clone();
+                for node in &nodes[1..] {
+                    let mv = tree.mv(*node).unwrap();
+                    texts.push(format!("{}. {}", texts.len(), describe(&board, mv)));
+                    board.apply(mv);
+                }
+            }
+            let last = entries - panel.scroll;
+            let first = last.saturating_sub(VISIBLE_ENTRIES);
+            for n in (first..last.min(texts.len())).rev() {
+                let node = nodes[n];
+                let color = if n == current {Color::rgba(0.2, 0.35, 0.5, 0.9)} else {background};
+                // Moves that were undone and can be played again
+                let text_color = if n > current {Color::rgb(0.55, 0.55, 0.55)} else {text_color};
+                // Other moves tried from the same position
+                let siblings = tree.parent(node).map(|parent| tree.children(parent)).unwrap_or_default();
+                let branch = (siblings.len() > 1).then(|| {
+                    let index = siblings.iter().position(|sibling| *sibling == node).unwrap();
+                    let next = siblings[(index + 1) % siblings.len()];
+                    (format!("branch {}/{} ›", index + 1, siblings.len()), BranchButton(next))
+                });
+                spawn_line(parent, font, &texts[n], color, text_color, Some(HistoryEntry(node)), branch);
+            }
+            if first > 0 {
+                spawn_line(parent, font, &format!("{} earlier", first), background, text_color, None::<HistoryEntry>, None);
+            }
+        });
+}
+
+/// Go down the line picked in the history, undoing back to where it branches off and then playing its moves,
+/// one at a time once the cards have stopped moving so each one animates
+pub fn navigate_system(
+    mut commands: Commands,
+    navigate: Option<Res<Navigate>>,
+    actions: Res<Actions>,
+    board: BoardEntities,
+    mut sent: Local<Option<usize>>,
+    mut ev_undo: EventWriter<UndoRequested>,
+    mut ev_released: EventWriter<Released>,
+    mut ev_dropped: EventWriter<Dropped>,
+    q_deck: Query<Entity, With<Deck>>,
+    q_children: Query<&Children>,
+    q_transform: Query<&Transform>,
+    q_easing: Query<(), With<EasingComponent<Transform>>>,
+    q_interaction: Query<&MouseInteraction>,
+) {
+    let target = if let Some(target) = navigate {target} else {
+        *sent = None;
+        return
+    };
+    if !q_easing.is_empty() || q_interaction.iter().any(|interaction| interaction.is_dragging()) {
+        return
+    }
+    // The move sent last time is played straight away, so nothing changing means it couldn't be
+    if sent.take() == Some(actions.0.len()) {
+        warn!("Couldn't play the next move of the line picked in the history");
+        commands.remove_resource::<Navigate>();
+        return
+    }
+    let current = board.moves(&actions.0);
+    if !target.0.starts_with(&current) {
+        ev_undo.send(UndoRequested);
+    } else if let (Some(mv), Ok(deck)) = (target.0.get(current.len()), q_deck.get_single()) {
+        send_move(*mv, &board, deck, &q_children, &q_transform, &mut ev_released, &mut ev_dropped);
+        *sent = Some(actions.0.len());
+    } else {
+        commands.remove_resource::<Navigate>();
+    }
+}
+
+pub fn hide_history(mut commands: Commands, q_root: Query<Entity, With<HistoryRoot>>) {
+    for entity in q_root.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
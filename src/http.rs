@@ -0,0 +1,382 @@
+//! Optional HTTP server on localhost for driving the game from other programs.
+//!
+//! * `GET /board` returns the position as JSON, along with the legal moves
+//! * `POST /move` plays a move written as in `notation`, either as the body or as `{"move": "t3-f"}`
+//! * `POST /undo` takes back the last action
+//! * `POST /new` deals a new game, optionally `{"draw": 3}`, from the menu or a game being played or won
+//! * `POST /restart` deals the current game again from the start
+//! * `GET /events` is a stream of server-sent events for every action taken in the game
+//!
+//! Requests are handed to the game through a channel and played through the same events as the mouse,
+//! so moves animate as if they had been dragged
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use bevy::prelude::*;
+use serde_json::{json, Value};
+
+use crate::game::{Action, Actions, BoardEntities, BoardPile, Card, CardFace, Deck, DrawMode, GameState, RestartRequested, UndoRequested};
+use crate::mouse_input::{Dropped, Released, send_move};
+use crate::notation::{card_code, format_move, parse_move, pile_code};
+
+pub const DEFAULT_PORT: u16 = 7878;
+/// Largest request body read, far more than any move or option needs
+const MAX_BODY: usize = 4096;
+/// Connections served at once, counting open event streams; more are turned away with 503
+const MAX_CONNECTIONS: usize = 16;
+
+#[derive(Debug, PartialEq)]
+enum ApiRequest {
+    Board,
+    Move(String),
+    Undo,
+    NewGame(Option<DrawMode>),
+    Restart,
+}
+
+#[derive(Debug)]
+struct Reply {
+    status: u16,
+    body: Value,
+}
+
+/// What a connection asked for
+#[derive(Debug, PartialEq)]
+enum Route {
+    /// A stream of server-sent events, kept open
+    Events,
+    Api(ApiRequest),
+}
+
+struct Request {
+    api: ApiRequest,
+    reply: Sender<Reply>,
+}
+
+/// Connection to the server threads
+#[derive(Resource)]
+pub struct HttpApi {
+    requests: Mutex<Receiver<Request>>,
+    /// Open event streams
+    subscribers: Arc<Mutex<Vec<Sender<String>>>>,
+}
+
+/// Start listening on `127.0.0.1:port` in the background
+pub fn start(port: u16) -> std::io::Result<HttpApi> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    info!("HTTP API listening on http://127.0.0.1:{}", port);
+    let (sender, receiver) = mpsc::channel();
+    let subscribers = Arc::new(Mutex::new(Vec::new()));
+    let streams = subscribers.clone();
+    let live = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            if live.load(Ordering::SeqCst) >= MAX_CONNECTIONS {
+                let _ = respond(&mut stream, 503, &json!({"error": "too many connections"}));
+                continue
+            }
+            live.fetch_add(1, Ordering::SeqCst);
+            let sender = sender.clone();
+            let streams = streams.clone();
+            let live = live.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_connection(stream, sender, streams) {
+                    debug!("HTTP connection closed: {}", e);
+                }
+                live.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+    Ok(HttpApi {
+        requests: Mutex::new(receiver),
+        subscribers,
+    })
+}
+
+/// Read a request's line, headers and body, and work out what it asks for. Requests that can't be
+/// answered by the game are turned into the reply to send back
+fn read_request(reader: &mut impl BufRead) -> std::io::Result<Result<Route, Reply>> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return Ok(Err(Reply {status: 400, body: json!({"error": format!("the body can't be over {} bytes", MAX_BODY)})}))
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8_lossy(&body).trim().to_string();
+
+    let mut parts = request_line.split_whitespace();
+    let api = match (parts.next().unwrap_or(""), parts.next().unwrap_or("")) {
+        ("GET", "/events") => return Ok(Ok(Route::Events)),
+        ("GET", "/board") => ApiRequest::Board,
+        ("POST", "/move") => {
+            let mv = serde_json::from_str::<Value>(&body)
+                .ok()
+                .and_then(|json| json.get("move").and_then(|mv| mv.as_str()).map(|mv| mv.to_string()))
+                .unwrap_or(body);
+            ApiRequest::Move(mv)
+        },
+        ("POST", "/undo") => ApiRequest::Undo,
+        ("POST", "/restart") => ApiRequest::Restart,
+        ("POST", "/new") => {
+            let draw = serde_json::from_str::<Value>(&body).ok().and_then(|json| json.get("draw").and_then(|draw| draw.as_u64()));
+            match draw {
+                None => ApiRequest::NewGame(None),
+                Some(1) => ApiRequest::NewGame(Some(DrawMode::Draw1)),
+                Some(3) => ApiRequest::NewGame(Some(DrawMode::Draw3)),
+                Some(n) => return Ok(Err(Reply {status: 400, body: json!({"error": format!("can't draw {} cards", n)})})),
+            }
+        },
+        _ => return Ok(Err(Reply {status: 404, body: json!({"error": "not found"})})),
+    };
+    Ok(Ok(Route::Api(api)))
+}
+
+fn handle_connection(stream: TcpStream, sender: Sender<Request>, subscribers: Arc<Mutex<Vec<Sender<String>>>>) -> std::io::Result<()> {
+    // Don't let a client that never finishes its request hold on to a connection
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+    let api = match read_request(&mut reader)? {
+        Ok(Route::Events) => {
+            let (events, received) = mpsc::channel();
+            subscribers.lock().unwrap().push(events);
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n")?;
+            stream.flush()?;
+            loop {
+                // A comment now and then finds out when the client has gone, freeing its connection
+                let line = match received.recv_timeout(Duration::from_secs(5)) {
+                    Ok(event) => format!("data: {}\n\n", event),
+                    Err(RecvTimeoutError::Timeout) => ":\n\n".to_string(),
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                };
+                stream.write_all(line.as_bytes())?;
+                stream.flush()?;
+            }
+        },
+        Ok(Route::Api(api)) => api,
+        Err(reply) => return respond(&mut stream, reply.status, &reply.body),
+    };
+    let (reply, replied) = mpsc::channel();
+    if sender.send(Request {api, reply}).is_err() {
+        return respond(&mut stream, 503, &json!({"error": "the game has closed"}))
+    }
+    match replied.recv_timeout(Duration::from_secs(5)) {
+        Ok(reply) => respond(&mut stream, reply.status, &reply.body),
+        Err(_) => respond(&mut stream, 503, &json!({"error": "the game did not answer"})),
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: u16, body: &Value) -> std::io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        409 => "Conflict",
+        _ => "Service Unavailable",
+    };
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, reason, body.len(), body
+    )?;
+    stream.flush()
+}
+
+/// Answer requests waiting from the server threads
+pub fn http_request_system(
+    mut commands: Commands,
+    api: Res<HttpApi>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut draw_mode: ResMut<DrawMode>,
+    board: BoardEntities,
+    mut ev_released: EventWriter<Released>,
+    mut ev_dropped: EventWriter<Dropped>,
+    mut ev_undo: EventWriter<UndoRequested>,
+    q_deck: Query<Entity, With<Deck>>,
+    q_children: Query<&Children>,
+    q_transform: Query<&Transform>,
+) {
+    let requests = api.requests.lock().unwrap();
+    while let Ok(request) = requests.try_recv() {
+        let playing = *state.get() == GameState::Playing;
+        let reply = match request.api {
+            ApiRequest::Board => match board.board() {
+                Some(current) => {
+                    let faces = |cards: &[(Card, CardFace)]| cards.iter()
+                        .map(|(card, face)| if *face == CardFace::Up {card_code(*card)} else {"##".to_string()})
+                        .collect::<Vec<_>>();
+                    let codes = |cards: &[Card]| cards.iter().map(|card| card_code(*card)).collect::<Vec<_>>();
+                    Reply {status: 200, body: json!({
+                        "state": format!("{:?}", state.get()),
+                        "draw": current.draw,
+                        "passes": current.passes,
+                        "recycles": current.recycles,
+                        "stock": current.stock.len(),
+                        "waste": codes(&current.waste),
+                        "reserve": faces(&current.reserve),
+                        "foundations": current.foundations.iter().map(|foundation| codes(&foundation.cards)).collect::<Vec<_>>(),
+                        "tableau": current.tableau.iter().map(|tableau| faces(&tableau.cards)).collect::<Vec<_>>(),
+                        "moves": current.moves().into_iter().map(|mv| format_move(&current, mv)).collect::<Vec<_>>(),
+                        "won": current.is_won(),
+                    })}
+                },
+                None => Reply {status: 409, body: json!({"error": "no game has been dealt"})},
+            },
+            ApiRequest::Move(_) if !playing => {
+                Reply {status: 409, body: json!({"error": format!("can't play while {:?}", state.get())})}
+            },
+            ApiRequest::Undo if matches!(state.get(), GameState::Menu | GameState::Shuffle | GameState::Paused) => {
+                Reply {status: 409, body: json!({"error": format!("can't play while {:?}", state.get())})}
+            },
+            ApiRequest::NewGame(_) if matches!(state.get(), GameState::AutoSolving | GameState::Shuffle | GameState::Paused | GameState::GameOver) => {
+                Reply {status: 409, body: json!({"error": format!("can't deal while {:?}", state.get())})}
+            },
+            // The deal's entities are spawned a frame after the state changes
+            ApiRequest::Move(text) => match (board.board(), q_deck.get_single()) {
+                (Some(current), Ok(deck)) => match parse_move(&current, &text) {
+                    Ok(mv) => {
+                        send_move(mv, &board, deck, &q_children, &q_transform, &mut ev_released, &mut ev_dropped);
+                        Reply {status: 202, body: json!({"move": format_move(&current, mv)})}
+                    },
+                    Err(e) => Reply {status: 400, body: json!({"error": e})},
+                },
+                _ => Reply {status: 409, body: json!({"error": "no game has been dealt"})},
+            },
+            ApiRequest::Undo => {
+                ev_undo.send(UndoRequested);
+                // Undoing out of a stuck game carries on playing it
+                if *state.get() == GameState::GameOver {
+                    next_state.set(GameState::Playing);
+                }
+                Reply {status: 202, body: json!({})}
+            },
+            ApiRequest::NewGame(draw) => {
+                if let Some(draw) = draw {
+                    *draw_mode = draw;
+                }
+                next_state.set(GameState::Shuffle);
+                Reply {status: 202, body: json!({})}
+            },
+            ApiRequest::Restart => {
+                if board.board().is_some() {
+                    commands.insert_resource(RestartRequested);
+                    next_state.set(GameState::Shuffle);
+                    Reply {status: 202, body: json!({})}
+                } else {
+                    Reply {status: 409, body: json!({"error": "no game has been dealt"})}
+                }
+            },
+        };
+        // The client may have hung up already
+        let _ = request.reply.send(reply);
+    }
+}
+
+/// Send every new action to the event streams
+pub fn http_event_system(
+    api: Res<HttpApi>,
+    actions: Res<Actions>,
+    state: Res<State<GameState>>,
+    mut last_len: Local<usize>,
+    q_piles: Query<&BoardPile>,
+) {
+    let mut events = Vec::new();
+    if state.is_changed() {
+        events.push(json!({"type": "state", "state": format!("{:?}", state.get())}));
+    }
+    if actions.0.len() < *last_len {
+        events.push(json!({"type": "undo", "actions": actions.0.len()}));
+    }
+    for action in actions.0.iter().skip(*last_len) {
+        events.push(match action {
+            Action::MoveCard {card, from, to, ..} => {
+                let pile = |entity: &Entity| q_piles.get(*entity).ok().map(|pile| pile_code(pile.0));
+                json!({"type": "move", "card": card_code(*card), "from": pile(from), "to": pile(to)})
+            },
+            Action::Draw(n) => json!({"type": "draw", "cards": n}),
+            Action::ResetDeck => json!({"type": "recycle"}),
+        });
+    }
+    *last_len = actions.0.len();
+    if events.is_empty() {
+        return
+    }
+    let mut subscribers = api.subscribers.lock().unwrap();
+    // Drop the streams that have been closed
+    subscribers.retain(|subscriber| events.iter().all(|event| subscriber.send(event.to_string()).is_ok()));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    fn read(request: &str) -> Result<Route, Reply> {
+        read_request(&mut Cursor::new(request)).unwrap()
+    }
+
+    fn post(path: &str, body: &str) -> Result<Route, Reply> {
+        read(&format!("POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}", path, body.len(), body))
+    }
+
+    fn status(request: Result<Route, Reply>) -> u16 {
+        request.unwrap_err().status
+    }
+
+    #[test]
+    fn routes() {
+        assert_eq!(read("GET /board HTTP/1.1\r\n\r\n").unwrap(), Route::Api(ApiRequest::Board));
+        assert_eq!(read("GET /events HTTP/1.1\r\n\r\n").unwrap(), Route::Events);
+        assert_eq!(post("/undo", "").unwrap(), Route::Api(ApiRequest::Undo));
+        assert_eq!(post("/restart", "").unwrap(), Route::Api(ApiRequest::Restart));
+        assert_eq!(status(read("GET /move HTTP/1.1\r\n\r\n")), 404);
+        assert_eq!(status(read("DELETE /board HTTP/1.1\r\n\r\n")), 404);
+        assert_eq!(status(read("")), 404);
+    }
+
+    #[test]
+    fn bodies() {
+        let mv = |text: &str| Route::Api(ApiRequest::Move(text.to_string()));
+        assert_eq!(post("/move", "{\"move\": \"t3-f\"}").unwrap(), mv("t3-f"));
+        assert_eq!(post("/move", " w-t5\n").unwrap(), mv("w-t5"));
+        // Headers are matched whatever their case
+        let lower = "POST /move HTTP/1.1\r\ncontent-length: 1\r\n\r\nd";
+        assert_eq!(read(lower).unwrap(), mv("d"));
+
+        assert_eq!(post("/new", "").unwrap(), Route::Api(ApiRequest::NewGame(None)));
+        assert_eq!(post("/new", "{\"draw\": 3}").unwrap(), Route::Api(ApiRequest::NewGame(Some(DrawMode::Draw3))));
+        let bad_draw = post("/new", "{\"draw\": 2}").unwrap_err();
+        assert_eq!((bad_draw.status, bad_draw.body), (400, json!({"error": "can't draw 2 cards"})));
+    }
+
+    #[test]
+    fn body_size_limit() {
+        let body = format!("{{\"move\": \"{}\"}}", "d".repeat(MAX_BODY - 12));
+        assert_eq!(body.len(), MAX_BODY);
+        assert!(post("/move", &body).is_ok());
+        // Turned away before anything is read
+        let request = format!("POST /move HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        assert_eq!(status(read(&request)), 400);
+    }
+}
@@ -0,0 +1,18 @@
+pub mod menus;
+pub mod mouse_input;
+pub mod game;
+pub mod variant;
+pub mod board;
+pub mod tree;
+pub mod solver;
+pub mod analyze;
+pub mod agent;
+pub mod notation;
+pub mod protocol;
+pub mod http;
+pub mod storage;
+pub mod daily;
+pub mod stats;
+pub mod pause;
+pub mod game_over;
+pub mod history;
@@ -2,18 +2,24 @@ use bevy::prelude::*;
 use bevy_easings::*;
 use bevy::window::PresentMode;
 
-mod menus;
-mod mouse_input;
-mod game;
+use solitare::{daily, game, game_over, history, http, menus, mouse_input, pause, protocol, stats, variant};
 
 fn main() {
-    App::new()
+    // Play over stdin and stdout instead of opening a window
+    if std::env::args().any(|arg| arg == "--protocol") {
+        let stdin = std::io::stdin();
+        protocol::run(stdin.lock(), std::io::stdout()).expect("failed to talk over stdio");
+        return
+    }
+
+    let mut app = App::new();
+    app
         .add_plugins(
             (
                 DefaultPlugins.set(WindowPlugin {
                     primary_window: Some(Window {
                         title: "Solitaire".to_string(),
-                        resolution: (1280., 960.).into(),
+                        resolution: (game::WINDOW_WIDTH, game::WINDOW_HEIGHT).into(),
                         present_mode: PresentMode::AutoVsync,
                         resizable: false,
                         ..Default::default()
@@ -25,20 +31,42 @@ fn main() {
         )
         .insert_resource(ClearColor(Color::rgb(0.3, 0.7, 0.1)))
         .insert_resource(game::DrawMode::Draw1)
+        .insert_resource(game::StockPasses::Unlimited)
+        .insert_resource(game::OpenDeal::default())
+        .insert_resource(game::WinnableDeals::default())
+        .insert_resource(game::AutoSolveBlocked::default())
         .insert_resource(game::Actions::default())
+        .insert_resource(game::UndoTree::default())
+        .insert_resource(game::GameClock::default())
+        .insert_resource(game::GameStats::default())
+        .insert_resource(game::AutoPlay::default())
+        .insert_resource(game::SmartClick::default())
+        .insert_resource(game::TakeBackPenalty(true))
+        .insert_resource(game::DropHighlight::default())
+        .insert_resource(daily::DailyResults::load())
+        .insert_resource(daily::CalendarMonth::default())
+        .insert_resource(stats::Statistics::load())
+        .insert_resource(pause::PausePanel::default())
+        .insert_resource(history::HistoryPanel::default())
         .add_state::<game::GameState>()
         .add_event::<mouse_input::Released>()
         .add_event::<mouse_input::Dropped>()
-        .add_systems(Startup, (game::setup, menus::setup_menu))
+        .add_event::<game::UndoRequested>()
+        .add_systems(Startup, (game::setup, (variant::load_variants, apply_deferred, menus::setup_menu).chain()))
         .add_systems(OnEnter(game::GameState::Menu), menus::show_menu)
-        .add_systems(Update, menus::main_menu.run_if(in_state(game::GameState::Menu)))
-        .add_systems(OnExit(game::GameState::Menu), menus::hide_menu)
-        .add_systems(OnEnter(game::GameState::Shuffle), (game::clean_cards, game::reset_cards))
+        .add_systems(Update, (menus::main_menu, daily::calendar_system).run_if(in_state(game::GameState::Menu)))
+        .add_systems(OnExit(game::GameState::Menu), (menus::hide_menu, daily::hide_calendar))
+        .add_systems(OnEnter(game::GameState::Shuffle), (game::clean_cards, game::shuffle_cards, menus::show_dealing))
+        .add_systems(Update, (game::reset_cards, game::cancel_dealing).chain().run_if(in_state(game::GameState::Shuffle)))
+        .add_systems(OnTransition {from: game::GameState::Shuffle, to: game::GameState::Menu}, menus::hide_reset_menu)
+        .add_systems(Update, menus::notice_system)
+        .add_systems(OnExit(game::GameState::Shuffle), menus::hide_dealing)
         .add_systems(
             Update,
             (
                 mouse_input::update_click_timers,
-                game::undo,
+                history::navigate_system,
+                game::undo.in_set(game::UndoSet),
                 // This is done before the mouse because the mouse can modify the deck to move cards from the deck to the discard
                 // If there is only 1 or 3 cards in the deck and no discard pile the win system will see it as a win this frame
                 // because the deck is empty but the new discard entities have not spawned yet.
@@ -46,24 +74,83 @@ fn main() {
                 game::win_check_system,
                 mouse_input::mouse_interaction_system,
                 mouse_input::click_system,
+                mouse_input::smart_click_system,
                 mouse_input::drop_system,
                 // Run this before the mouse system so the double click sets the translation to the
                 // completed pile rather than the discard pile resetting it
                 // It would nice to make this event based so its not running constantly anyways
                 apply_deferred,
+                mouse_input::drop_highlight_system,
+                game::reserve_fill_system,
+                game::auto_play_system,
                 game::discard_update_system,
                 game::card_texture_update_system,
                 game::deck_update_system,
+                game::stock_preview_system,
                 menus::reset_game_button,
             ).chain().run_if(in_state(game::GameState::Playing))
         )
-        .add_systems(Update, game::auto_solver.run_if(in_state(game::GameState::AutoSolving)))
+        .add_systems(Update, history::tree_system.before(history::history_system))
+        .add_systems(Update, (game::undo.in_set(game::UndoSet), game::auto_solver).chain().run_if(in_state(game::GameState::AutoSolving)))
+        .add_systems(
+            Update,
+            game::resume_play_system
+                .after(game::UndoSet)
+                .after(menus::reset_game_button)
+                .before(game::auto_solver)
+                .run_if(in_state(game::GameState::Playing).or_else(in_state(game::GameState::AutoSolving)).or_else(in_state(game::GameState::Won)))
+        )
+        .add_systems(
+            Update,
+            (game::save_record_system, game::export_layout_system).run_if(in_state(game::GameState::Playing).or_else(in_state(game::GameState::Won)))
+        )
         .add_systems(
             PreUpdate,
             mouse_input::clickable_bounds_update_system
         )
-        .add_systems(OnEnter(game::GameState::Won), menus::spawn_win_screen)
-        .add_systems(Update, menus::win_screen.run_if(in_state(game::GameState::Won)))
-        .add_systems(OnExit(game::GameState::Won), game::clean_cards)
-        .run();
+        .add_systems(
+            Update,
+            (game::game_clock_system, daily::daily_started_system, stats::stats_started_system, pause::pause_system, game_over::stuck_system, history::history_system)
+                .run_if(in_state(game::GameState::Playing))
+        )
+        .add_systems(OnEnter(game::GameState::Paused), pause::freeze_animations)
+        .add_systems(Update, (pause::spawn_pause_menu, pause::pause_menu).run_if(in_state(game::GameState::Paused)))
+        .add_systems(OnExit(game::GameState::Paused), (pause::despawn_pause_menu, pause::resume_animations))
+        .add_systems(OnTransition {from: game::GameState::Paused, to: game::GameState::Menu}, (game::clean_cards, menus::hide_reset_menu))
+        .add_systems(OnEnter(game::GameState::GameOver), game_over::spawn_game_over)
+        .add_systems(Update, game_over::game_over_menu.run_if(in_state(game::GameState::GameOver)))
+        .add_systems(OnExit(game::GameState::GameOver), game_over::despawn_game_over)
+        .add_systems(OnTransition {from: game::GameState::GameOver, to: game::GameState::Menu}, (game::clean_cards, menus::hide_reset_menu))
+        .add_systems(Update, game::hud_system.run_if(in_state(game::GameState::Playing).or_else(in_state(game::GameState::AutoSolving))))
+        .add_systems(OnEnter(game::GameState::Won), (menus::spawn_win_screen, daily::daily_won_system, stats::stats_won_system))
+        .add_systems(Update, (menus::win_screen, game::undo.in_set(game::UndoSet)).chain().run_if(in_state(game::GameState::Won)))
+        .add_systems(OnExit(game::GameState::Won), menus::despawn_win_screen)
+        .add_systems(OnTransition {from: game::GameState::Won, to: game::GameState::Menu}, game::clean_cards)
+        .add_systems(OnTransition {from: game::GameState::Won, to: game::GameState::Playing}, menus::show_reset_menu)
+        .add_systems(OnExit(game::GameState::Playing), (game::hide_stock_preview, history::hide_history));
+
+    // Control API for other programs, with an optional port after the flag
+    let mut args = std::env::args().skip_while(|arg| arg != "--http");
+    if args.next().is_some() {
+        let port = args.next().and_then(|port| port.parse().ok()).unwrap_or(http::DEFAULT_PORT);
+        match http::start(port) {
+            Ok(api) => {
+                app.insert_resource(api)
+                    .add_systems(Update, (http::http_request_system, http::http_event_system));
+            },
+            Err(e) => error!("Failed to start the HTTP API on port {}: {}", port, e),
+        }
+    }
+
+    // Deal the first game from a layout file instead of shuffling
+    let mut args = std::env::args().skip_while(|arg| arg != "--layout");
+    if let (Some(_), Some(path)) = (args.next(), args.next()) {
+        match std::fs::read_to_string(&path) {
+            Ok(layout) => {
+                app.insert_resource(game::ImportedLayout(layout));
+            },
+            Err(e) => error!("Failed to read the layout {}: {}", path, e),
+        }
+    }
+    app.run();
 }
@@ -1,16 +1,31 @@
 use bevy::prelude::*;
 use bevy::ui::Display;
 
-use crate::game::{self, DrawMode, FontHandle};
+use crate::game::{self, DrawMode, FontHandle, GameClock, GameStats, OpenDeal, RestartRequested, StockPasses, WinnableDeals};
+use crate::variant::{Variant, Variants};
+use crate::daily::{DAILY_VARIANT, DailyRequested};
 
 #[derive(Component)]
 pub struct WinText;
 
+#[derive(Component)]
+pub struct WinButton;
+
+/// Shown while searching for a winnable deal
+#[derive(Component)]
+pub struct DealingText;
+
 #[derive(Component)]
 pub enum MenuButton {
     Play,
+    Daily,
     Draw1,
-    Draw3
+    Draw3,
+    Passes(StockPasses),
+    OpenDeal,
+    Winnable,
+    /// Index into `Variants`
+    Variant(usize),
 }
 
 #[derive(Component)]
@@ -20,13 +35,50 @@ pub struct MenuRoot;
 pub enum ResetButton {
     Draw1,
     Draw3,
+    /// Deal the same cards again
+    Restart,
 }
 
 #[derive(Component)]
 pub struct ResetMenuRoot;
 
 
-pub fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>, mut reset_menu: Query<&mut Style, With<ResetMenuRoot>>) {
+fn spawn_menu_button(parent: &mut ChildBuilder, font: &Handle<Font>, label: &str, top: Val, bottom: Val, button: MenuButton) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(250.0),
+                height: Val::Px(65.0),
+                margin: UiRect {
+                    top,
+                    bottom,
+                    left: Val::Auto,
+                    right: Val::Auto,
+                },
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+            ..Default::default()
+        })
+        .insert(button)
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    label,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 40.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+pub fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>, variants: Res<Variants>, mut reset_menu: Query<&mut Style, With<ResetMenuRoot>>) {
     let font_handle = asset_server.load("fonts/FiraSans-Bold.ttf");
 
     commands
@@ -44,102 +96,18 @@ pub fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>, mut re
         })
         .insert(MenuRoot)
         .with_children(|parent| {
-            parent
-                .spawn(ButtonBundle {
-                    style: Style {
-                        width: Val::Px(175.0),
-                        height: Val::Px(65.0),
-                        margin: UiRect {
-                            left: Val::Auto,
-                            right: Val::Auto,
-                            top: Val::Auto,
-                            bottom: Val::Px(10.0),
-                        },
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..Default::default()
-                    },
-                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
-                    ..Default::default()
-                })
-                .insert(MenuButton::Play)
-                .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                            "Play",
-                            TextStyle {
-                                font: font_handle.clone(),
-                                font_size: 40.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
-                            }
-                        ),
-                        ..Default::default()
-                    });
-                });
-            parent
-                .spawn(ButtonBundle {
-                    style: Style {
-                        width: Val::Px(175.0),
-                        height: Val::Px(65.0),
-                        margin: UiRect {
-                            top: Val::Px(1.0),
-                            bottom: Val::Px(1.0),
-                            left: Val::Auto,
-                            right: Val::Auto,
-                        },
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..Default::default()
-                    },
-                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
-                    ..Default::default()
-                })
-                .insert(MenuButton::Draw1)
-                .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                            "Draw One",
-                            TextStyle {
-                                font: font_handle.clone(),
-                                font_size: 40.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
-                            },
-                        ),
-                        ..Default::default()
-                    });
-                });
-            parent
-                .spawn(ButtonBundle {
-                    style: Style {
-                        width: Val::Px(175.0),
-                        height: Val::Px(65.0),
-                        margin: UiRect {
-                            top: Val::Px(1.0),
-                            bottom: Val::Auto,
-                            left: Val::Auto,
-                            right: Val::Auto,
-                        },
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..Default::default()
-                    },
-                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
-                    ..Default::default()
-                })
-                .insert(MenuButton::Draw3)
-                .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                            "Draw Three",
-                            TextStyle {
-                                font: font_handle.clone(),
-                                font_size: 40.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
-                            },
-                        ),
-                        ..Default::default()
-                    });
-                });
+            spawn_menu_button(parent, &font_handle, "Play", Val::Auto, Val::Px(1.0), MenuButton::Play);
+            spawn_menu_button(parent, &font_handle, "Daily Deal", Val::Px(1.0), Val::Px(10.0), MenuButton::Daily);
+            spawn_menu_button(parent, &font_handle, "Draw One", Val::Px(1.0), Val::Px(1.0), MenuButton::Draw1);
+            spawn_menu_button(parent, &font_handle, "Draw Three", Val::Px(1.0), Val::Px(10.0), MenuButton::Draw3);
+            spawn_menu_button(parent, &font_handle, "Free Passes", Val::Px(1.0), Val::Px(1.0), MenuButton::Passes(StockPasses::Unlimited));
+            spawn_menu_button(parent, &font_handle, "Vegas Passes", Val::Px(1.0), Val::Px(10.0), MenuButton::Passes(StockPasses::Vegas));
+            spawn_menu_button(parent, &font_handle, "Thoughtful", Val::Px(1.0), Val::Px(1.0), MenuButton::OpenDeal);
+            spawn_menu_button(parent, &font_handle, "Winnable Only", Val::Px(1.0), Val::Px(10.0), MenuButton::Winnable);
+            for (i, variant) in variants.0.iter().enumerate() {
+                let bottom = if i + 1 == variants.0.len() {Val::Auto} else {Val::Px(1.0)};
+                spawn_menu_button(parent, &font_handle, &variant.name, Val::Px(1.0), bottom, MenuButton::Variant(i));
+            }
         });
 
     for mut style in reset_menu.iter_mut() {
@@ -160,7 +128,13 @@ pub fn hide_menu(mut menu_root: Query<&mut Style, With<MenuRoot>>) {
 }
 
 pub fn main_menu(
+    mut commands: Commands,
     mut draw_mode: ResMut<DrawMode>,
+    mut stock_passes: ResMut<StockPasses>,
+    mut open_deal: ResMut<OpenDeal>,
+    mut winnable: ResMut<WinnableDeals>,
+    mut variant: ResMut<Variant>,
+    variants: Res<Variants>,
     mut game_state: ResMut<NextState<game::GameState>>,
     interaction_query: Query<(&MenuButton, &Interaction), Changed<Interaction>>,
     mut q_buttons: Query<(&MenuButton, &mut BackgroundColor)>,
@@ -172,18 +146,41 @@ pub fn main_menu(
                     MenuButton::Play => {
                         game_state.set(game::GameState::Shuffle);
                     },
+                    MenuButton::Daily => {
+                        // Everyone plays the daily deal with the same rules
+                        if let Some(klondike) = variants.0.iter().find(|variant| variant.name == DAILY_VARIANT) {
+                            *variant = klondike.clone();
+                        }
+                        *draw_mode = DrawMode::Draw1;
+                        *stock_passes = StockPasses::Unlimited;
+                        open_deal.0 = false;
+                        commands.insert_resource(DailyRequested);
+                        game_state.set(game::GameState::Shuffle);
+                    },
                     MenuButton::Draw1 => {
                         *draw_mode = DrawMode::Draw1;
                     },
                     MenuButton::Draw3 => {
                         *draw_mode = DrawMode::Draw3;
                     },
+                    MenuButton::Passes(passes) => {
+                        *stock_passes = *passes;
+                    },
+                    MenuButton::OpenDeal => {
+                        open_deal.0 = !open_deal.0;
+                    },
+                    MenuButton::Winnable => {
+                        winnable.0 = !winnable.0;
+                    },
+                    MenuButton::Variant(i) => {
+                        *variant = variants.0[*i].clone();
+                    },
                 }
             },
             _ => {},
         }
     }
-    if draw_mode.is_changed() {
+    if draw_mode.is_changed() || stock_passes.is_changed() || open_deal.is_changed() || winnable.is_changed() || variant.is_changed() {
         for (button, mut color) in q_buttons.iter_mut() {
             match button {
                 MenuButton::Draw1 => {
@@ -200,38 +197,126 @@ pub fn main_menu(
                         *color = Color::rgb(0.5, 0.5, 0.5).into();
                     }
                 },
-                MenuButton::Play => {},
+                MenuButton::Passes(passes) => {
+                    if *stock_passes == *passes {
+                        *color = Color::rgb(0.15, 0.15, 0.15).into();
+                    } else {
+                        *color = Color::rgb(0.5, 0.5, 0.5).into();
+                    }
+                },
+                MenuButton::OpenDeal => {
+                    if open_deal.0 {
+                        *color = Color::rgb(0.15, 0.15, 0.15).into();
+                    } else {
+                        *color = Color::rgb(0.5, 0.5, 0.5).into();
+                    }
+                },
+                MenuButton::Winnable => {
+                    if winnable.0 {
+                        *color = Color::rgb(0.15, 0.15, 0.15).into();
+                    } else {
+                        *color = Color::rgb(0.5, 0.5, 0.5).into();
+                    }
+                },
+                MenuButton::Variant(i) => {
+                    if variant.name == variants.0[*i].name {
+                        *color = Color::rgb(0.15, 0.15, 0.15).into();
+                    } else {
+                        *color = Color::rgb(0.5, 0.5, 0.5).into();
+                    }
+                },
+                MenuButton::Play | MenuButton::Daily => {},
             }
         }
     }
 }
 
+pub fn show_dealing(mut commands: Commands, winnable: Res<WinnableDeals>, font: Res<FontHandle>) {
+    if !winnable.0 {
+        return
+    }
+    commands.spawn(Text2dBundle {
+            text: Text::from_section(
+                "Dealing...\nEscape to cancel",
+                TextStyle {
+                    font: font.0.clone(),
+                    font_size: 60.0,
+                    color: Color::WHITE,
+                }
+            ).with_alignment(TextAlignment::Center),
+            transform: Transform::from_xyz(0.0, 0.0, 100.0),
+            ..Default::default()
+        })
+        .insert(DealingText);
+}
+
+/// Message shown over the table for a few seconds
+#[derive(Component)]
+pub struct Notice(Timer);
+
+pub fn spawn_notice(commands: &mut Commands, font: &Handle<Font>, text: &str) {
+    commands.spawn(Text2dBundle {
+            text: Text::from_section(
+                text,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 36.0,
+                    color: Color::WHITE,
+                }
+            ).with_alignment(TextAlignment::Center),
+            transform: Transform::from_xyz(0.0, 0.0, 100.0),
+            ..Default::default()
+        })
+        .insert(Notice(Timer::from_seconds(4.0, TimerMode::Once)));
+}
+
+pub fn notice_system(mut commands: Commands, time: Res<Time>, mut q_notice: Query<(Entity, &mut Notice)>) {
+    for (entity, mut notice) in q_notice.iter_mut() {
+        if notice.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn hide_dealing(mut commands: Commands, q_text: Query<Entity, With<DealingText>>) {
+    for entity in q_text.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
 pub fn win_screen(
-    mut commands: Commands,
     mut game_state: ResMut<NextState<game::GameState>>,
-    interaction_query: Query<(Entity, &Interaction), (Changed<Interaction>, With<Button>)>,
-    win_text: Query<Entity, With<WinText>>,
-
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<WinButton>)>,
 ) {
-    for (entity, interaction) in interaction_query.iter() {
+    for interaction in interaction_query.iter() {
         match *interaction {
             Interaction::Pressed => {
                 game_state.set(game::GameState::Menu);
-                commands.entity(entity).despawn_recursive();
-                for e in win_text.iter() {
-                    commands.entity(e).despawn_recursive();
-                }
             },
             _ => {},
         }
     }
 }
 
+/// Clear the win screen, whether going on to the menu or undoing back into the game
+pub fn despawn_win_screen(mut commands: Commands, q_win: Query<Entity, Or<(With<WinText>, With<WinButton>)>>) {
+    for entity in q_win.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
 pub fn reset_game_button(
+    mut commands: Commands,
     mut game_state: ResMut<NextState<game::GameState>>,
     mut draw_mode: ResMut<DrawMode>,
+    keys: Res<Input<KeyCode>>,
     interaction_query: Query<(&Interaction, &ResetButton), (Changed<Interaction>, With<Button>)>,
 ) {
+    // ctrl+R to restart
+    if keys.just_pressed(KeyCode::R) && keys.pressed(KeyCode::ControlLeft) {
+        commands.insert_resource(RestartRequested);
+        game_state.set(game::GameState::Shuffle);
+    }
     for (interaction, button) in interaction_query.iter() {
         match *interaction {
             Interaction::Pressed => {
@@ -242,6 +327,9 @@ pub fn reset_game_button(
                     ResetButton::Draw3 => {
                         *draw_mode = DrawMode::Draw3;
                     },
+                    ResetButton::Restart => {
+                        commands.insert_resource(RestartRequested);
+                    },
                 }
                 game_state.set(game::GameState::Shuffle);
             },
@@ -254,6 +342,8 @@ pub fn spawn_win_screen(
     mut commands: Commands,
     windows: Query<&Window>,
     font: Res<FontHandle>,
+    stats: Res<GameStats>,
+    clock: Res<GameClock>,
     mut reset_menu: Query<&mut Style, With<ResetMenuRoot>>,
 ) {
     let window = if let Ok(w) = windows.get_single() {w} else {return};
@@ -283,6 +373,19 @@ pub fn spawn_win_screen(
                 transform: Transform::from_xyz(3.0, -3.0, -1.0),
                 ..Default::default()
             });
+            parent.spawn(Text2dBundle {
+                text: Text::from_section(
+                    stats.summary(&clock),
+                    TextStyle {
+                        font: font.0.clone(),
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                    }
+                )
+                .with_alignment(TextAlignment::Center),
+                transform: Transform::from_xyz(0.0, -90.0, 0.0),
+                ..Default::default()
+            });
         })
         .insert(WinText);
 
@@ -307,6 +410,7 @@ pub fn spawn_win_screen(
             background_color: Color::rgb(0.15, 0.15, 0.15).into(),
             ..Default::default()
         })
+        .insert(WinButton)
         .with_children(|parent| {
             parent.spawn(TextBundle {
                 text: Text::from_section(
@@ -325,3 +429,17 @@ pub fn spawn_win_screen(
         style.display = Display::None;
     }
 }
+
+/// Bring the bar at the bottom of the table back after undoing out of the win screen
+pub fn show_reset_menu(mut reset_menu: Query<&mut Style, With<ResetMenuRoot>>) {
+    for mut style in reset_menu.iter_mut() {
+        style.display = Display::Flex;
+    }
+}
+
+/// Hide the bar at the bottom of the table when leaving a game for the main menu
+pub fn hide_reset_menu(mut reset_menu: Query<&mut Style, With<ResetMenuRoot>>) {
+    for mut style in reset_menu.iter_mut() {
+        style.display = Display::None;
+    }
+}
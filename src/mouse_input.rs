@@ -15,15 +15,26 @@ use crate::game::{
     Stack,
     StackKind,
     CardsTextureHandle,
-    DrawMode,
     Area,
     move_card,
     top_entity,
     bottom_entity,
     walk,
+    walk_children,
     Action,
     Actions,
+    Deal,
+    BoardEntities,
+    SmartClick,
+    DropHighlight,
 };
+use crate::board::{Board, Move, Pile};
+use crate::variant::Variant;
+
+const TARGET_TINT: Color = Color::rgb(0.75, 0.9, 1.0);
+const HOVERED_TINT: Color = Color::rgb(0.5, 0.75, 1.0);
+const TARGET_OUTLINE: Color = Color::rgb(0.95, 0.95, 0.95);
+const HOVERED_OUTLINE: Color = Color::GOLD;
 
 #[derive(Debug, Component)]
 pub struct Clickable {
@@ -92,7 +103,7 @@ impl MouseInteraction {
 pub struct Clicked(Entity, Vec2);
 
 #[derive(Debug, Clone, Event)]
-pub struct Released(Entity, Vec2);
+pub struct Released(pub Entity, pub Vec2);
 
 #[derive(Debug, Component)]
 pub struct WasClicked(Timer);
@@ -105,8 +116,9 @@ pub struct Droppable {
     pub zone: Area,
 }
 
+/// A card was let go of. The last field names the pile it has to go on, otherwise it goes on whichever pile it was dropped over
 #[derive(Debug, Event)]
-pub struct Dropped(Entity, Vec3, Vec2);
+pub struct Dropped(pub Entity, pub Vec3, pub Vec2, pub Option<Entity>);
 
 pub fn clickable_bounds_update_system(
     mut clickables: Query<(&mut Clickable, &GlobalTransform), Changed<GlobalTransform>>,
@@ -159,7 +171,7 @@ pub fn mouse_interaction_system(
                     commands.entity(entity).remove::<MouseInteraction>();
                 },
                 MouseInteraction::Dragging {local_start_pos, ..} => {
-                    ev_dropped.send(Dropped(entity, local_start_pos, mouse_position));
+                    ev_dropped.send(Dropped(entity, local_start_pos, mouse_position, None));
                     commands.entity(entity).remove::<MouseInteraction>();
                 },
                 _ => {}
@@ -221,7 +233,7 @@ pub fn mouse_interaction_system(
 pub fn click_system(
     mut commands: Commands,
     mut actions: ResMut<Actions>,
-    draw_mode: ResMut<DrawMode>,
+    deal: Res<Deal>,
     mut ev_released: EventReader<Released>,
     card_texture: Res<CardsTextureHandle>,
     q_card: Query<&Card>,
@@ -234,10 +246,15 @@ pub fn click_system(
     q_discard: Query<Entity, With<DiscardPile>>,
     q_gtransform: Query<&GlobalTransform>,
     mut q_transform: Query<&mut Transform>,
+    smart_click: Res<SmartClick>,
 ) {
     for Released(entity, _offset) in ev_released.iter() {
         if let Ok(mut deck) = q_deck.get_mut(*entity) {
             if deck.cards.len() == 0 {
+                if deal.board.passes.map(|passes| deck.recycles + 1 >= passes).unwrap_or(false) {
+                    // Out of passes through the deck
+                    continue
+                }
                 let discard = q_discard.single();
                 let top = top_entity(discard, &q_children);
                 let top = if top == discard {
@@ -255,6 +272,7 @@ pub fn click_system(
                         commands.entity(*child).despawn_recursive();
                     }
                 }
+                deck.recycles += 1;
                 actions.0.push(Action::ResetDeck);
             } else {
                 let discard = q_discard.single();
@@ -265,9 +283,10 @@ pub fn click_system(
                     // Make the current top card undraggable
                     commands.entity(top).remove::<Draggable>();
                 }
-                let num_drawn = deck.cards.len().min(draw_mode.num());
+                let draw_num = deal.board.draw;
+                let num_drawn = deck.cards.len().min(draw_num);
                 actions.0.push(Action::Draw(num_drawn));
-                for _ in 0..draw_mode.num() {
+                for _ in 0..draw_num {
                     match deck.cards.pop() {
                         Some(card) => {
                             let new = commands.spawn(SpriteSheetBundle {
@@ -296,28 +315,32 @@ pub fn click_system(
         if let Ok(card) = q_card.get(*entity) {
             let face = q_card_face.get(*entity).unwrap();
             match face {
+                // Moved by smart_click_system instead
+                CardFace::Up if smart_click.0 => {},
                 CardFace::Up => {
                     let has_children = q_children.get(*entity).ok().map(|c| !c.is_empty()).unwrap_or(false);
-                    if q_was_clicked.get(*entity).is_ok() && !has_children {
+                    let on_foundation = q_stacks.get(bottom_entity(*entity, &q_parent)).map(|(_, stack)| stack.kind != StackKind::Stack).unwrap_or(false);
+                    if q_was_clicked.get(*entity).is_ok() && !has_children && !on_foundation {
                         // double click
                         commands.entity(*entity).remove::<WasClicked>();
-                        if let Some((stack_entity, stack)) = q_stacks.iter().filter(|(_, stack)| stack.kind == StackKind::Ordered(card.suit)).nth(0) {
+                        let target = q_stacks
+                            .iter()
+                            .filter(|(_, stack)| stack.kind == StackKind::Ordered(card.suit))
+                            .find(|(stack_entity, stack)| stack.can_stack(q_card.get(top_entity(*stack_entity, &q_children)).ok(), *card, false));
+                        if let Some((stack_entity, _)) = target {
                             let target = top_entity(stack_entity, &q_children);
-                            let target_card = q_card.get(target).ok();
-                            if stack.can_stack(target_card, *card, false) {
-                                // If we get in here the top card should always have a parent
-                                // However,
-                                // TODO: Handle the case where the dropped card was taken from the discard pile
-                                actions.0.push(Action::MoveCard {
-                                    card: card.clone(),
-                                    from: bottom_entity(*entity, &q_parent),
-                                    to: stack_entity,
-                                    y_offset: q_transform.get(*entity).map(|t| t.translation.y).unwrap_or(CARD_STACK_SPACE),
-                                    parent_face_down: q_parent.get(*entity).map(|p| q_card_face.get(p.get()).ok() == Some(&CardFace::Down)).unwrap_or(false),
-                                });
-                                move_card(&mut commands, &q_parent, &q_gtransform, &mut q_transform, &q_card, &q_card_face, *entity, target, 0.0, 100);
-                                break
-                            }
+                            // If we get in here the top card should always have a parent
+                            // However,
+                            // TODO: Handle the case where the dropped card was taken from the discard pile
+                            actions.0.push(Action::MoveCard {
+                                card: card.clone(),
+                                from: bottom_entity(*entity, &q_parent),
+                                to: stack_entity,
+                                y_offset: q_transform.get(*entity).map(|t| t.translation.y).unwrap_or(CARD_STACK_SPACE),
+                                parent_face_down: q_parent.get(*entity).map(|p| q_card_face.get(p.get()).ok() == Some(&CardFace::Down)).unwrap_or(false),
+                            });
+                            move_card(&mut commands, &q_parent, &q_gtransform, &mut q_transform, &q_card, &q_card_face, *entity, target, 0.0, 100);
+                            break
                         }
                     } else {
                         commands.entity(*entity).insert(WasClicked(Timer::from_seconds(0.5, TimerMode::Once)));
@@ -337,10 +360,184 @@ pub fn click_system(
     }
 }
 
+/// Where the last smart click sent a card, so clicking it again sends it on to the next place
+pub struct SmartCycle {
+    card: Card,
+    /// Places the card could go from where it was first clicked, ending with that pile
+    targets: Vec<Pile>,
+    /// The place it was sent to
+    index: usize,
+    /// Number of actions once it got there, so any other move starts over
+    actions: usize,
+}
+
+/// Places a card and the cards on it can go, best first: a foundation, then onto another card,
+/// then an empty pile. Only one empty pile of each kind is offered since they're all the same
+fn smart_targets(board: &Board, from: Pile, count: usize) -> Vec<Pile> {
+    let mut targets: Vec<Pile> = board
+        .moves()
+        .into_iter()
+        .filter_map(|mv| match mv {
+            Move::Cards {from: f, to, count: c} if f == from && c == count => Some(to),
+            _ => None,
+        })
+        .collect();
+    targets.sort_by_key(|to| match to {
+        Pile::Foundation(_) => 0,
+        _ if board.top(*to).is_some() => 1,
+        _ => 2,
+    });
+    let (mut empty_foundation, mut empty_tableau) = (false, false);
+    targets.retain(|to| {
+        if board.top(*to).is_some() {
+            return true
+        }
+        let seen = if let Pile::Foundation(_) = to {&mut empty_foundation} else {&mut empty_tableau};
+        !std::mem::replace(seen, true)
+    });
+    targets
+}
+
+/// Move a clicked card, and the cards on it, to the best place it can go when smart click is on.
+/// Clicking it again straight away tries the next best place, back around to where it started
+pub fn smart_click_system(
+    smart_click: Res<SmartClick>,
+    actions: Res<Actions>,
+    board: BoardEntities,
+    mut cycle: Local<Option<SmartCycle>>,
+    mut ev_released: EventReader<Released>,
+    mut ev_dropped: EventWriter<Dropped>,
+    q_card: Query<&Card>,
+    q_card_face: Query<&CardFace>,
+    q_parent: Query<&Parent>,
+    q_children: Query<&Children>,
+    q_transform: Query<&Transform>,
+) {
+    if !smart_click.0 {
+        ev_released.clear();
+        return
+    }
+    for Released(entity, _offset) in ev_released.iter() {
+        let card = match (q_card.get(*entity), q_card_face.get(*entity)) {
+            (Ok(card), Ok(CardFace::Up)) => *card,
+            _ => continue,
+        };
+        let from = if let Some(pile) = board.pile(bottom_entity(*entity, &q_parent)) {pile} else {continue};
+        let current = if let Some(b) = board.board() {b} else {continue};
+        let mut count = 0;
+        walk_children(Some(*entity), &q_children, &mut |_| count += 1);
+        let moves = current.moves();
+        let legal = |to: Pile| moves.contains(&Move::Cards {from, to, count});
+        let next = match cycle.take() {
+            Some(mut last) if last.card == card && last.actions == actions.0.len() && last.targets[last.index] == from => {
+                let len = last.targets.len();
+                (1..len).map(|i| (last.index + i) % len).find(|i| legal(last.targets[*i])).map(|i| {
+                    last.index = i;
+                    last
+                })
+            },
+            // Cards only come off a foundation when cycling on from it
+            _ if matches!(from, Pile::Foundation(_)) => None,
+            _ => {
+                let mut targets = smart_targets(&current, from, count);
+                targets.push(from);
+                if targets.len() > 1 {Some(SmartCycle {card, targets, index: 0, actions: 0})} else {None}
+            },
+        };
+        if let Some(mut next) = next {
+            let start = q_transform.get(*entity).map(|t| t.translation).unwrap_or_default();
+            ev_dropped.send(Dropped(*entity, start, Vec2::ZERO, board.entity(next.targets[next.index])));
+            // drop_system adds the move this frame
+            next.actions = actions.0.len() + 1;
+            *cycle = Some(next);
+        }
+    }
+}
+
+/// Play a move of the model through the same events as the mouse, so it animates as if the cards had been dragged
+pub fn send_move(
+    mv: Move,
+    board: &BoardEntities,
+    deck: Entity,
+    q_children: &Query<&Children>,
+    q_transform: &Query<&Transform>,
+    ev_released: &mut EventWriter<Released>,
+    ev_dropped: &mut EventWriter<Dropped>,
+) {
+    match mv {
+        Move::Draw | Move::Recycle => ev_released.send(Released(deck, Vec2::ZERO)),
+        Move::Cards {from, to, count} => {
+            let base = if let Some(base) = board.entity(from) {base} else {return};
+            let mut pile = Vec::new();
+            walk_children(Some(base), q_children, &mut |e| pile.push(e));
+            let card = pile[pile.len() - count];
+            let start = q_transform.get(card).map(|t| t.translation).unwrap_or_default();
+            ev_dropped.send(Dropped(card, start, Vec2::ZERO, board.entity(to)));
+        },
+    }
+}
+
+/// Where a dragged card would go on a pile, as the entity it would go on and how far down from it,
+/// or `None` if it can't go there
+fn drop_spot(
+    dropped: Entity,
+    pile: Entity,
+    variant: &Variant,
+    q_children: &Query<&Children>,
+    q_parent: &Query<&Parent>,
+    q_card: &Query<&Card>,
+    q_stack: &Query<&Stack>,
+) -> Option<(Entity, f32)> {
+    // Find the bottom of the drop stack
+    let top = top_entity(pile, q_children);
+    // If the entity is not a card we will get None
+    let top_card = q_card.get(top).ok();
+    let stack = q_stack.get(pile).ok()?;
+    let dropped_card = q_card.get(dropped).ok()?;
+    let has_children = q_children.get(dropped).ok().map(|c| !c.is_empty()).unwrap_or(false);
+    if has_children && variant.single_card_moves {
+        return None
+    }
+    // Only the top card of a completed pile can be taken back down, and only onto the tableau
+    let from_foundation = q_stack.get(bottom_entity(dropped, q_parent)).map(|stack| stack.kind != StackKind::Stack).unwrap_or(false);
+    if from_foundation && (has_children || stack.kind != StackKind::Stack || !variant.foundation_moves) {
+        return None
+    }
+    // Face up cards only form a run in a normal deal
+    let mut run = Vec::new();
+    walk_children(Some(dropped), q_children, &mut |e| run.extend(q_card.get(e).ok()));
+    if stack.kind == StackKind::Stack && !stack.rules.is_run(&run) {
+        return None
+    }
+    if !stack.can_stack(top_card, *dropped_card, has_children) {
+        return None
+    }
+    let end_y = match stack.kind {
+        StackKind::Stack => {
+            if top_card.is_some() {
+                -CARD_STACK_SPACE
+            } else {
+                0.0
+            }
+        },
+        StackKind::Ordered(_) => {
+            0.0
+        }
+    };
+    Some((top, end_y))
+}
+
+/// Compare two piles a card could be dropped on by how much of the card is over each one, then by
+/// how close the cursor is to them when it's over both just as much
+fn drop_order((overlap_a, distance_a): (f32, f32), (overlap_b, distance_b): (f32, f32)) -> std::cmp::Ordering {
+    overlap_a.total_cmp(&overlap_b).then(distance_b.total_cmp(&distance_a))
+}
+
 pub fn drop_system(
     mut commands: Commands,
     mut ev_dropped: EventReader<Dropped>,
     mut actions: ResMut<Actions>,
+    variant: Res<Variant>,
     q_droppable: Query<(Entity, &Droppable)>,
     q_children: Query<&Children>,
     q_parent: Query<&Parent>,
@@ -350,45 +547,33 @@ pub fn drop_system(
     mut q_transform: Query<&mut Transform>,
     q_global_transform: Query<&GlobalTransform>,
 ) {
-    for Dropped(dropped, local_start_pos, _mouse_position) in ev_dropped.iter() {
+    for Dropped(dropped, local_start_pos, mouse_position, target) in ev_dropped.iter() {
         let pos3 = q_global_transform.get(*dropped).unwrap().translation();
-        let pos = Vec2::new(pos3.x, pos3.y);
-        let mut was_dropped = false;
+        let card_area = Area::new(pos3.x - CARD_WIDTH / 2.0, pos3.y - CARD_HEIGHT / 2.0, CARD_WIDTH, CARD_HEIGHT);
+        // If we are not dragging a card this will fail
+        let dropped_card = q_card.get(*dropped).unwrap();
+        // Every pile the card could legally go on, with how much of the card is over it and how far the cursor is from it
+        let mut candidates = Vec::new();
         for (droppable_entity, droppable) in q_droppable.iter() {
-            if droppable.zone.contains(pos) {
-                // Find the bottom of the drop stack
-                let top = top_entity(droppable_entity, &q_children);
-                // If the entity is not a card we will get None
-                let top_card = q_card.get(top).ok();
-                let stack = q_stack.get(droppable_entity).unwrap();
-                // If we are not dragging a card this will fail
-                let dropped_card = q_card.get(*dropped).unwrap();
-                let has_children = q_children.get(*dropped).ok().map(|c| !c.is_empty()).unwrap_or(false);
-                if stack.can_stack(top_card, *dropped_card, has_children) {
-                    let end_y = match stack.kind {
-                        StackKind::Stack => {
-                            if top_card.is_some() {
-                                -CARD_STACK_SPACE
-                            } else {
-                                0.0
-                            }
-                        },
-                        StackKind::Ordered(_) => {
-                            0.0
-                        }
-                    };
-                    actions.0.push(Action::MoveCard {
-                        card: dropped_card.clone(),
-                        from: bottom_entity(*dropped, &q_parent),
-                        to: droppable_entity,
-                        y_offset: local_start_pos.y,
-                        parent_face_down: q_parent.get(*dropped).map(|p| q_card_face.get(p.get()).ok() == Some(&CardFace::Down)).unwrap_or(false)
-                    });
-                    move_card(&mut commands, &q_parent, &q_global_transform, &mut q_transform, &q_card, &q_card_face, *dropped, top, end_y, 50);
-                    was_dropped = true;
-                    break
-                }
+            let overlap = droppable.zone.overlap(&card_area);
+            if !target.map(|target| target == droppable_entity).unwrap_or(overlap > 0.0) {
+                continue
             }
+            if let Some((top, end_y)) = drop_spot(*dropped, droppable_entity, &variant, &q_children, &q_parent, &q_card, &q_stack) {
+                candidates.push((droppable_entity, top, end_y, overlap, droppable.zone.distance(*mouse_position)));
+            }
+        }
+        let best = candidates.into_iter().max_by(|a, b| drop_order((a.3, a.4), (b.3, b.4)));
+        let was_dropped = best.is_some();
+        if let Some((droppable_entity, top, end_y, _, _)) = best {
+            actions.0.push(Action::MoveCard {
+                card: dropped_card.clone(),
+                from: bottom_entity(*dropped, &q_parent),
+                to: droppable_entity,
+                y_offset: local_start_pos.y,
+                parent_face_down: q_parent.get(*dropped).map(|p| q_card_face.get(p.get()).ok() == Some(&CardFace::Down)).unwrap_or(false)
+            });
+            move_card(&mut commands, &q_parent, &q_global_transform, &mut q_transform, &q_card, &q_card_face, *dropped, top, end_y, 50);
         }
         if !was_dropped {
             // Move back to the old position
@@ -414,3 +599,156 @@ pub fn update_click_timers(mut commands: Commands, time: Res<Time>, mut timers:
         }
     }
 }
+
+/// Border around a pile a dragged card can go on
+#[derive(Component)]
+pub struct DropOutline;
+
+/// The piles the card being dragged can go on, worked out when the drag starts, and how they are picked out
+#[derive(Default)]
+pub struct DragTargets {
+    dragged: Option<Entity>,
+    /// Each pile with the entity the card would go on
+    piles: Vec<(Entity, Entity)>,
+    /// The pile the card would land on if let go of now
+    hovered: Option<Entity>,
+    /// Each pile with the outline around it
+    outlines: Vec<(Entity, Entity)>,
+    /// Sprites tinted, to put back when the drag ends
+    tinted: Vec<Entity>,
+}
+
+fn outline_style(hovered: bool) -> (Color, f32) {
+    if hovered {(HOVERED_OUTLINE, 6.0)} else {(TARGET_OUTLINE, 3.0)}
+}
+
+/// Size and position of the four bars of an outline around a card
+fn outline_bars(width: f32) -> [(Vec2, Vec3); 4] {
+    let (half_x, half_y) = ((CARD_WIDTH + width) / 2.0, (CARD_HEIGHT + width) / 2.0);
+    [
+        (Vec2::new(CARD_WIDTH + 2.0 * width, width), Vec3::new(0.0, half_y, 0.0)),
+        (Vec2::new(CARD_WIDTH + 2.0 * width, width), Vec3::new(0.0, -half_y, 0.0)),
+        (Vec2::new(width, CARD_HEIGHT), Vec3::new(-half_x, 0.0, 0.0)),
+        (Vec2::new(width, CARD_HEIGHT), Vec3::new(half_x, 0.0, 0.0)),
+    ]
+}
+
+fn spawn_outline(commands: &mut Commands, pos: Vec3, hovered: bool) -> Entity {
+    let (color, width) = outline_style(hovered);
+    commands
+        .spawn(SpatialBundle::from_transform(Transform::from_translation(pos)))
+        .insert(DropOutline)
+        .with_children(|parent| {
+            for (size, translation) in outline_bars(width) {
+                parent.spawn(SpriteBundle {
+                    sprite: Sprite {color, custom_size: Some(size), ..Default::default()},
+                    transform: Transform::from_translation(translation),
+                    ..Default::default()
+                });
+            }
+        })
+        .id()
+}
+
+/// Show where the card being dragged can go, picking out the pile it would land on if let go of now.
+/// Everything is drawn when the drag starts, after that only the piles moved onto and off are changed
+pub fn drop_highlight_system(
+    mut commands: Commands,
+    mode: Res<DropHighlight>,
+    variant: Res<Variant>,
+    mut targets: Local<DragTargets>,
+    q_interaction: Query<(Entity, &MouseInteraction, &Transform)>,
+    q_droppable: Query<(Entity, &Droppable)>,
+    q_children: Query<&Children>,
+    q_parent: Query<&Parent>,
+    q_card: Query<&Card>,
+    q_stack: Query<&Stack>,
+    q_gtransform: Query<&GlobalTransform>,
+    mut q_sprite: Query<&mut TextureAtlasSprite>,
+    mut q_bar: Query<(&mut Sprite, &mut Transform), Without<MouseInteraction>>,
+) {
+    let dragging = q_interaction.iter().find(|(_, interaction, _)| interaction.is_dragging());
+    let new_drag = dragging.map(|(entity, _, _)| entity) != targets.dragged || mode.is_changed();
+    if new_drag {
+        for entity in targets.tinted.drain(..) {
+            if let Ok(mut sprite) = q_sprite.get_mut(entity) {
+                sprite.color = Color::WHITE;
+            }
+        }
+        for (_, outline) in targets.outlines.drain(..) {
+            commands.entity(outline).despawn_recursive();
+        }
+        targets.dragged = None;
+        targets.hovered = None;
+    }
+    let (dragged, local_start_pos, start_mouse, transform) = match dragging {
+        Some((entity, MouseInteraction::Dragging {local_start_pos, start_mouse, ..}, transform)) => (entity, *local_start_pos, *start_mouse, transform),
+        _ => return,
+    };
+    if new_drag {
+        targets.dragged = Some(dragged);
+        targets.piles = q_droppable
+            .iter()
+            .filter_map(|(pile, _)| drop_spot(dragged, pile, &variant, &q_children, &q_parent, &q_card, &q_stack).map(|(top, _)| (pile, top)))
+            .collect();
+    }
+    if *mode == DropHighlight::Off {
+        return
+    }
+    // The same pile drop_system would pick
+    let pos = if let Ok(g) = q_gtransform.get(dragged) {g.translation()} else {return};
+    let card_area = Area::new(pos.x - CARD_WIDTH / 2.0, pos.y - CARD_HEIGHT / 2.0, CARD_WIDTH, CARD_HEIGHT);
+    let cursor = start_mouse + (transform.translation - local_start_pos).truncate();
+    let hovered = targets.piles
+        .iter()
+        .filter_map(|(pile, _)| {
+            let zone = &q_droppable.get(*pile).ok()?.1.zone;
+            let overlap = zone.overlap(&card_area);
+            (overlap > 0.0).then(|| (*pile, (overlap, zone.distance(cursor))))
+        })
+        .max_by(|a, b| drop_order(a.1, b.1))
+        .map(|(pile, _)| pile);
+    if !new_drag && hovered == targets.hovered {
+        return
+    }
+
+    // Every target when the drag starts, otherwise just the piles moved off and onto
+    let changed: Vec<(Entity, Entity)> = if new_drag {
+        targets.piles.clone()
+    } else {
+        targets.piles.iter().copied().filter(|(pile, _)| Some(*pile) == hovered || Some(*pile) == targets.hovered).collect()
+    };
+    for (pile, top) in changed {
+        let is_hovered = hovered == Some(pile);
+        match *mode {
+            DropHighlight::Off => {},
+            DropHighlight::Tint => {
+                if let Ok(mut sprite) = q_sprite.get_mut(top) {
+                    sprite.color = if is_hovered {HOVERED_TINT} else {TARGET_TINT};
+                    if new_drag {
+                        targets.tinted.push(top);
+                    }
+                }
+            },
+            DropHighlight::Outline if new_drag => {
+                if let Ok(g) = q_gtransform.get(top) {
+                    let outline = spawn_outline(&mut commands, g.translation() + Vec3::Z * 0.5, is_hovered);
+                    targets.outlines.push((pile, outline));
+                }
+            },
+            DropHighlight::Outline => {
+                let outline = targets.outlines.iter().find(|(outlined, _)| *outlined == pile).map(|(_, outline)| *outline);
+                let bars = if let Some(bars) = outline.and_then(|outline| q_children.get(outline).ok()) {bars} else {continue};
+                let (color, width) = outline_style(is_hovered);
+                for (bar, (size, translation)) in bars.iter().zip(outline_bars(width)) {
+                    if let Ok((mut sprite, mut transform)) = q_bar.get_mut(*bar) {
+                        sprite.color = color;
+                        sprite.custom_size = Some(size);
+                        transform.translation = translation;
+                    }
+                }
+            },
+        }
+    }
+    targets.hovered = hovered;
+}
@@ -0,0 +1,632 @@
+//! Text forms of cards and moves.
+//!
+//! Cards are a rank and a suit, `As`, `Td`, `Qh`. Moves are `d` to draw, `r` to turn the waste back over, or a
+//! source and destination pile such as `w-t5`, `t3-f` or `t2:3-t6` to move three cards from the second tableau
+//! pile onto the sixth. Piles are `w` for the waste, `r` for the reserve, `t<n>` for tableau piles and `f<n>` for
+//! foundations, counting from 1. A plain `f` means whichever foundation the card goes on.
+//!
+//! Starting positions are written as layouts, one pile per line, and whole games as a `GameRecord`,
+//! along with any other lines tried
+
+use std::fmt;
+
+use crate::board::{Board, Move, Pile, shuffled_deck};
+use crate::tree::MoveTree;
+use crate::game::{Card, CardFace, CardKind, Suit, new_deck};
+use crate::variant::{EmptyRule, PileKind, Variant};
+
+pub fn card_code(card: Card) -> String {
+    let rank = match card.kind {
+        CardKind::Ace => 'A',
+        CardKind::Number(10) => 'T',
+        CardKind::Number(n) => char::from_digit(n as u32, 10).unwrap_or('?'),
+        CardKind::Jack => 'J',
+        CardKind::Queen => 'Q',
+        CardKind::King => 'K',
+    };
+    let suit = match card.suit {
+        Suit::Spades => 's',
+        Suit::Clubs => 'c',
+        Suit::Diamonds => 'd',
+        Suit::Hearts => 'h',
+    };
+    format!("{}{}", rank, suit)
+}
+
+pub fn parse_card(code: &str) -> Result<Card, String> {
+    let mut chars = code.chars();
+    let (rank, suit) = match (chars.next(), chars.next(), chars.next()) {
+        (Some(rank), Some(suit), None) => (rank, suit),
+        _ => return Err(format!("{:?} is not a card", code)),
+    };
+    let kind = match rank.to_ascii_uppercase() {
+        'A' => CardKind::Ace,
+        'T' => CardKind::Number(10),
+        'J' => CardKind::Jack,
+        'Q' => CardKind::Queen,
+        'K' => CardKind::King,
+        n @ '2'..='9' => CardKind::Number(n.to_digit(10).unwrap() as usize),
+        _ => return Err(format!("{:?} has no rank {:?}", code, rank)),
+    };
+    let suit = match suit.to_ascii_lowercase() {
+        's' => Suit::Spades,
+        'c' => Suit::Clubs,
+        'd' => Suit::Diamonds,
+        'h' => Suit::Hearts,
+        _ => return Err(format!("{:?} has no suit {:?}", code, suit)),
+    };
+    Ok(Card {suit, kind})
+}
+
+pub fn pile_code(pile: Pile) -> String {
+    match pile {
+        Pile::Waste => "w".to_string(),
+        Pile::Reserve => "r".to_string(),
+        Pile::Foundation(i) => format!("f{}", i + 1),
+        Pile::Tableau(i) => format!("t{}", i + 1),
+    }
+}
+
+/// The foundation a plain `f` refers to when moving `card`
+fn any_foundation(board: &Board, card: Card) -> Option<usize> {
+    (0..board.foundations.len()).find(|i| board.accepts(Pile::Foundation(*i), card, 1))
+}
+
+pub fn format_move(board: &Board, mv: Move) -> String {
+    match mv {
+        Move::Draw => "d".to_string(),
+        Move::Recycle => "r".to_string(),
+        Move::Cards {from, to, count} => {
+            let count = if count > 1 {format!(":{}", count)} else {String::new()};
+            let to = match to {
+                Pile::Foundation(i) if board.top(from).and_then(|card| any_foundation(board, card)) == Some(i) => "f".to_string(),
+                to => pile_code(to),
+            };
+            format!("{}{}-{}", pile_code(from), count, to)
+        },
+    }
+}
+
+fn parse_pile(board: &Board, code: &str) -> Result<Pile, String> {
+    let index = |kind: &str, n: &str, len: usize| match n.parse::<usize>() {
+        Ok(n) if n >= 1 && n <= len => Ok(n - 1),
+        _ => Err(format!("there is no {} {:?}", kind, n)),
+    };
+    match code.split_at(code.len().min(1)) {
+        ("w", "") => Ok(Pile::Waste),
+        ("r", "") => Ok(Pile::Reserve),
+        ("t", n) => index("tableau pile", n, board.tableau.len()).map(Pile::Tableau),
+        ("f", n) => index("foundation", n, board.foundations.len()).map(Pile::Foundation),
+        _ => Err(format!("unknown pile {:?}", code)),
+    }
+}
+
+/// Read a move and check it can be made on the board
+pub fn parse_move(board: &Board, text: &str) -> Result<Move, String> {
+    let text = text.trim().to_ascii_lowercase();
+    let mv = match text.as_str() {
+        "d" => Move::Draw,
+        "r" => Move::Recycle,
+        _ => {
+            let (from, to) = text.split_once('-').ok_or_else(|| format!("{:?} is not a move", text))?;
+            let (from, count) = match from.split_once(':') {
+                Some((from, count)) => (from, count.parse::<usize>().map_err(|_| format!("bad card count {:?}", count))?),
+                None => (from, 1),
+            };
+            let from = parse_pile(board, from)?;
+            let to = if to == "f" {
+                let card = board.top(from).ok_or_else(|| format!("{} is empty", pile_code(from)))?;
+                Pile::Foundation(any_foundation(board, card).ok_or_else(|| format!("{} can't go on a foundation", card_code(card)))?)
+            } else {
+                parse_pile(board, to)?
+            };
+            Move::Cards {from, to, count}
+        },
+    };
+    if board.moves().contains(&mv) {
+        Ok(mv)
+    } else {
+        Err(format!("{} is not a legal move", text))
+    }
+}
+
+/// A position written out one pile per line, e.g.
+///
+/// ```text
+/// variant Klondike
+/// stock 4c 9h Ks ...
+/// waste
+/// f1
+/// t1 7d
+/// t2 #Qs 2h
+/// ```
+///
+/// The stock is listed in the order it will be drawn and every other pile from the bottom up, with `#` in front
+/// of face down cards. Lines can also be separated with `;` so a layout fits on one line
+pub fn format_layout(variant: &str, board: &Board) -> String {
+    let faces = |cards: &[(Card, CardFace)]| cards.iter()
+        .map(|(card, face)| if *face == CardFace::Down {format!("#{}", card_code(*card))} else {card_code(*card)})
+        .collect::<Vec<_>>();
+    let codes = |cards: &mut dyn Iterator<Item = &Card>| cards.map(|card| card_code(*card)).collect::<Vec<_>>();
+    let mut lines = vec![
+        format!("variant {}", variant),
+        format!("stock {}", codes(&mut board.stock.iter().rev()).join(" ")),
+        format!("waste {}", codes(&mut board.waste.iter()).join(" ")),
+    ];
+    if !board.reserve.is_empty() {
+        lines.push(format!("reserve {}", faces(&board.reserve).join(" ")));
+    }
+    for (i, foundation) in board.foundations.iter().enumerate() {
+        lines.push(format!("f{} {}", i + 1, codes(&mut foundation.cards.iter()).join(" ")));
+    }
+    for (i, tableau) in board.tableau.iter().enumerate() {
+        lines.push(format!("t{} {}", i + 1, faces(&tableau.cards).join(" ")));
+    }
+    lines.into_iter().map(|line| line.trim_end().to_string()).collect::<Vec<_>>().join("\n")
+}
+
+/// Name of the variant a layout says it is for
+pub fn layout_variant(text: &str) -> Option<String> {
+    text.split(['\n', ';'])
+        .find_map(|line| line.trim().strip_prefix("variant "))
+        .map(|name| name.trim().to_string())
+}
+
+/// Read a layout for the variant, checking every card of its decks appears exactly once
+pub fn parse_layout(variant: &Variant, draw: usize, passes: Option<usize>, text: &str) -> Result<Board, String> {
+    // Dealt normally for the shape of the piles, then every card is replaced
+    let mut board = Board::deal(variant, draw, passes, false, shuffled_deck(variant.decks, 0));
+    board.stock.clear();
+    board.reserve.clear();
+    board.foundations.iter_mut().for_each(|foundation| foundation.cards.clear());
+    board.tableau.iter_mut().for_each(|tableau| tableau.cards.clear());
+
+    let mut seen = Vec::new();
+    for line in text.split(['\n', ';']).map(|line| line.trim()).filter(|line| !line.is_empty()) {
+        let mut words = line.split_whitespace();
+        let pile = words.next().unwrap().to_ascii_lowercase();
+        if pile == "variant" {
+            let name = words.collect::<Vec<_>>().join(" ");
+            if !name.eq_ignore_ascii_case(&variant.name) {
+                return Err(format!("the layout is for {} not {}", name, variant.name))
+            }
+            continue
+        }
+        let mut cards = Vec::new();
+        for word in words {
+            let (code, face) = match word.strip_prefix('#') {
+                Some(code) => (code, CardFace::Down),
+                None => (word, CardFace::Up),
+            };
+            let card = parse_card(code)?;
+            seen.push(card);
+            cards.push((card, face));
+        }
+        let up = |cards: Vec<(Card, CardFace)>| cards.into_iter().map(|(card, _)| card).collect::<Vec<_>>();
+        match pile.as_str() {
+            "stock" => board.stock = up(cards).into_iter().rev().collect(),
+            "waste" => board.waste = up(cards),
+            "reserve" if variant.piles.iter().any(|pile| pile.kind == PileKind::Reserve) => board.reserve = cards,
+            _ => match parse_pile(&board, &pile)? {
+                Pile::Foundation(i) => board.foundations[i].cards = up(cards),
+                Pile::Tableau(i) => board.tableau[i].cards = cards,
+                _ => return Err(format!("{} has no {}", variant.name, pile)),
+            },
+        }
+    }
+
+    let mut problems = Vec::new();
+    for card in new_deck() {
+        let count = seen.iter().filter(|seen| **seen == card).count();
+        if count < variant.decks {
+            problems.push(format!("{} is missing", card_code(card)));
+        } else if count > variant.decks {
+            problems.push(format!("{} appears {} times", card_code(card), count));
+        }
+    }
+    if !problems.is_empty() {
+        return Err(problems.join(", "))
+    }
+
+    // The completed piles of Canfield start from whichever rank was dealt to them
+    let first_dealt = variant.piles.iter()
+        .any(|pile| pile.kind == PileKind::Foundation && pile.build.map(|build| build.empty) == Some(EmptyRule::FirstDealt));
+    if first_dealt {
+        let first = board.foundations.iter().find_map(|foundation| foundation.cards.first())
+            .ok_or_else(|| format!("{} needs a card on the completed piles to start from", variant.name))?;
+        let rank = first.kind;
+        board.foundations.iter_mut().for_each(|foundation| foundation.rules.empty = EmptyRule::Rank(rank));
+    }
+    for (i, foundation) in board.foundations.iter().enumerate() {
+        let mut below = None;
+        for card in foundation.cards.iter() {
+            if card.suit != foundation.suit || !foundation.rules.accepts(below, *card) {
+                return Err(format!("{} can't go on f{}", card_code(*card), i + 1))
+            }
+            below = Some(card);
+        }
+    }
+    Ok(board)
+}
+
+/// A game written down as tags followed by its moves, e.g.
+///
+/// ```text
+/// [Variant "Klondike"]
+/// [Seed "42"]
+/// [Draw "3"]
+/// [Passes "unlimited"]
+/// [Open "false"]
+/// [Result "won"]
+///
+/// d w-t5 t3-f t2:3-t6 ...
+/// ```
+///
+/// Other lines tried along the way follow the move they were played instead of in brackets, as in PGN:
+/// `d w-t5 (t3-f t1-t2) t3-f` tried `t3-f t1-t2` instead of `w-t5`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameRecord {
+    pub variant: String,
+    pub seed: Option<u64>,
+    /// Starting layout on one line, for games that weren't dealt from a seed
+    pub layout: Option<String>,
+    pub draw: usize,
+    pub passes: Option<usize>,
+    pub open: bool,
+    /// `won` or `unfinished`
+    pub result: String,
+    pub moves: Vec<String>,
+    pub variations: Vec<Variation>,
+}
+
+/// A line played instead of one of the moves of another line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variation {
+    /// Index of the move it replaces
+    pub at: usize,
+    pub moves: Vec<String>,
+    pub variations: Vec<Variation>,
+}
+
+/// Write down the line starting at `node`, from the position `board` before it. The lines that branch off it are
+/// written as variations, apart from those off its first move when it's a variation itself
+fn write_line(tree: &MoveTree, board: &Board, node: usize, branch_first: bool) -> (Vec<String>, Vec<Variation>, Board) {
+    let mut board = board.clone();
+    let mut moves = Vec::new();
+    let mut variations = Vec::new();
+    for node in tree.continuation(node) {
+        let parent = tree.parent(node).unwrap_or(MoveTree::ROOT);
+        if branch_first || !moves.is_empty() {
+            for sibling in tree.children(parent).iter().filter(|sibling| **sibling != node) {
+                let (sub_moves, sub_variations, _) = write_line(tree, &board, *sibling, false);
+                variations.push(Variation {at: moves.len(), moves: sub_moves, variations: sub_variations});
+            }
+        }
+        let mv = tree.mv(node).unwrap();
+        moves.push(format_move(&board, mv));
+        board.apply(mv);
+    }
+    (moves, variations, board)
+}
+
+/// Read a line up to the bracket that closes it, or the end for the main line
+fn parse_line(tokens: &[&str], at: &mut usize, nested: bool) -> Result<(Vec<String>, Vec<Variation>), String> {
+    let mut moves = Vec::new();
+    let mut variations = Vec::new();
+    while let Some(token) = tokens.get(*at) {
+        *at += 1;
+        match *token {
+            "(" => {
+                let replaced = moves.len().checked_sub(1).ok_or("a variation has to follow the move it replaces")?;
+                let (sub_moves, sub_variations) = parse_line(tokens, at, true)?;
+                variations.push(Variation {at: replaced, moves: sub_moves, variations: sub_variations});
+            },
+            ")" if nested => return Ok((moves, variations)),
+            ")" => return Err("a bracket closes a variation that wasn't opened".to_string()),
+            mv => moves.push(mv.to_string()),
+        }
+    }
+    if nested {
+        return Err("a variation isn't closed".to_string())
+    }
+    Ok((moves, variations))
+}
+
+/// Play a line through from `board`, checking its variations from the positions they branch off
+fn play_line(board: &Board, moves: &[String], variations: &[Variation]) -> Result<Board, String> {
+    let mut board = board.clone();
+    for (n, text) in moves.iter().enumerate() {
+        for variation in variations.iter().filter(|variation| variation.at == n) {
+            play_line(&board, &variation.moves, &variation.variations).map_err(|e| format!("variation at move {}: {}", n + 1, e))?;
+        }
+        let mv = parse_move(&board, text).map_err(|e| format!("move {}: {}", n + 1, e))?;
+        board.apply(mv);
+    }
+    Ok(board)
+}
+
+/// Moves of a line with its variations in brackets after the moves they replace
+fn line_tokens(moves: &[String], variations: &[Variation]) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for (n, mv) in moves.iter().enumerate() {
+        tokens.push(mv.clone());
+        for variation in variations.iter().filter(|variation| variation.at == n) {
+            tokens.push(format!("({})", line_tokens(&variation.moves, &variation.variations).join(" ")));
+        }
+    }
+    tokens
+}
+
+fn result(board: &Board) -> String {
+    if board.is_won() {"won".to_string()} else {"unfinished".to_string()}
+}
+
+impl GameRecord {
+    /// Write down the moves played from `start`, checking each one is legal. Without a seed the layout is written down too
+    pub fn new(variant: &str, seed: Option<u64>, open: bool, start: &Board, moves: &[Move]) -> Result<Self, String> {
+        let mut board = start.clone();
+        let mut written = Vec::new();
+        for (n, mv) in moves.iter().enumerate() {
+            if !board.moves().contains(mv) {
+                return Err(format!("move {} ({:?}) is not legal", n + 1, mv))
+            }
+            written.push(format_move(&board, *mv));
+            board.apply(*mv);
+        }
+        Ok(Self {
+            variant: variant.to_string(),
+            seed,
+            layout: seed.is_none().then(|| format_layout(variant, start).replace('\n', "; ")),
+            draw: start.draw,
+            passes: start.passes,
+            open,
+            result: result(&board),
+            moves: written,
+            variations: Vec::new(),
+        })
+    }
+
+    /// Write down every line in the tree. The main line goes through the position being played and on down the
+    /// moves last undone from it
+    pub fn from_tree(variant: &str, seed: Option<u64>, open: bool, start: &Board, tree: &MoveTree) -> Self {
+        let (moves, variations, board) = match tree.next(MoveTree::ROOT) {
+            Some(first) => write_line(tree, start, first, true),
+            None => (Vec::new(), Vec::new(), start.clone()),
+        };
+        Self {
+            variant: variant.to_string(),
+            seed,
+            layout: seed.is_none().then(|| format_layout(variant, start).replace('\n', "; ")),
+            draw: start.draw,
+            passes: start.passes,
+            open,
+            result: result(&board),
+            moves,
+            variations,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut tags = Vec::new();
+        let mut text_moves = String::new();
+        for line in text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+            if let Some(tag) = line.strip_prefix('[').and_then(|tag| tag.strip_suffix(']')) {
+                let (name, value) = tag.split_once(' ').ok_or_else(|| format!("tag {:?} has no value", tag))?;
+                let value = value.trim().strip_prefix('"').and_then(|value| value.strip_suffix('"'))
+                    .ok_or_else(|| format!("tag {} should be quoted", name))?;
+                tags.push((name.to_ascii_lowercase(), value.to_string()));
+            } else {
+                text_moves.push_str(&line.replace('(', " ( ").replace(')', " ) "));
+                text_moves.push(' ');
+            }
+        }
+        let tokens: Vec<&str> = text_moves.split_whitespace().collect();
+        let (moves, variations) = parse_line(&tokens, &mut 0, false)?;
+        let tag = |name: &str| tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str());
+        let number = |name: &str, value: &str| value.parse::<usize>().map_err(|_| format!("{} {:?} is not a number", name, value));
+        Ok(Self {
+            variant: tag("variant").ok_or("missing Variant tag")?.to_string(),
+            seed: tag("seed").map(|seed| seed.parse::<u64>().map_err(|_| format!("seed {:?} is not a number", seed))).transpose()?,
+            layout: tag("layout").map(|layout| layout.to_string()),
+            draw: number("draw", tag("draw").ok_or("missing Draw tag")?)?,
+            passes: match tag("passes") {
+                None | Some("unlimited") => None,
+                Some(passes) => Some(number("passes", passes)?),
+            },
+            open: tag("open") == Some("true"),
+            result: tag("result").unwrap_or("unfinished").to_string(),
+            moves,
+            variations,
+        })
+    }
+
+    /// Deal the game again and play the moves through, returning where they end up.
+    /// Fails on the first illegal move, in the main line or any variation, or if the result doesn't match
+    pub fn replay(&self, variants: &[Variant]) -> Result<Board, String> {
+        let variant = variants.iter()
+            .find(|variant| variant.name.eq_ignore_ascii_case(&self.variant))
+            .ok_or_else(|| format!("no variant named {:?}", self.variant))?;
+        let mut board = match (self.seed, &self.layout) {
+            (Some(seed), _) => Board::deal(variant, self.draw, self.passes, self.open, shuffled_deck(variant.decks, seed)),
+            (None, Some(layout)) => parse_layout(variant, self.draw, self.passes, layout)?,
+            (None, None) => return Err("the record has no seed or layout to deal from".to_string()),
+        };
+        board = play_line(&board, &self.moves, &self.variations)?;
+        if result(&board) != self.result {
+            return Err(format!("the game is {} but the record says {}", result(&board), self.result))
+        }
+        Ok(board)
+    }
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[Variant \"{}\"]", self.variant)?;
+        if let Some(seed) = self.seed {
+            writeln!(f, "[Seed \"{}\"]", seed)?;
+        }
+        if let Some(layout) = &self.layout {
+            writeln!(f, "[Layout \"{}\"]", layout)?;
+        }
+        writeln!(f, "[Draw \"{}\"]", self.draw)?;
+        writeln!(f, "[Passes \"{}\"]", self.passes.map(|passes| passes.to_string()).unwrap_or_else(|| "unlimited".to_string()))?;
+        writeln!(f, "[Open \"{}\"]", self.open)?;
+        writeln!(f, "[Result \"{}\"]", self.result)?;
+        writeln!(f)?;
+        for line in line_tokens(&self.moves, &self.variations).chunks(12) {
+            writeln!(f, "{}", line.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::fixtures::{card, klondike, variant};
+    use crate::variant::read_variants;
+
+    /// `start` followed by the legal move at each index in `picks`, wrapping around the list of moves
+    fn play(board: &Board, start: &[Move], picks: &[usize]) -> Vec<Move> {
+        let mut board = board.clone();
+        start.iter().for_each(|mv| board.apply(*mv));
+        let mut moves = start.to_vec();
+        for pick in picks {
+            let legal = board.moves();
+            let mv = legal[pick % legal.len()];
+            board.apply(mv);
+            moves.push(mv);
+        }
+        moves
+    }
+
+    #[test]
+    fn record_round_trip() {
+        let start = klondike(42);
+        // More than fit on one line of the record
+        let moves = play(&start, &[], &[0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2]);
+        let record = GameRecord::new("Klondike", Some(42), false, &start, &moves).unwrap();
+        assert_eq!(record.moves.len(), moves.len());
+
+        let text = record.to_string();
+        let parsed = GameRecord::parse(&text).unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.to_string(), text);
+        let end = parsed.replay(&read_variants()).unwrap();
+        let mut board = start.clone();
+        moves.iter().for_each(|mv| board.apply(*mv));
+        assert_eq!(end.key(), board.key());
+    }
+
+    #[test]
+    fn record_rejects_illegal_moves() {
+        let start = klondike(42);
+        let recycle = GameRecord::new("Klondike", Some(42), false, &start, &[Move::Recycle]);
+        assert!(recycle.unwrap_err().starts_with("move 1 "));
+
+        let moves = play(&start, &[], &[0, 0, 0]);
+        let mut record = GameRecord::new("Klondike", Some(42), false, &start, &moves).unwrap();
+        record.moves[1] = "t1-t2".to_string();
+        assert!(record.replay(&read_variants()).unwrap_err().starts_with("move 2: "));
+        let mut record = GameRecord::new("Klondike", Some(42), false, &start, &moves).unwrap();
+        record.result = "won".to_string();
+        assert_eq!(record.replay(&read_variants()).unwrap_err(), "the game is unfinished but the record says won");
+    }
+
+    #[test]
+    fn variations_round_trip() {
+        let start = klondike(42);
+        let main = play(&start, &[], &[0, 0, 0, 0, 0, 0]);
+        let other = play(&start, &[], &[1, 0, 0, 0]);
+        let nested = play(&start, &other[..1], &[1, 0]);
+        let mut tree = MoveTree::default();
+        for line in [&main, &other, &nested, &main] {
+            tree.follow(line);
+        }
+        let record = GameRecord::from_tree("Klondike", Some(42), false, &start, &tree);
+        assert_eq!(record.moves.len(), main.len());
+        assert_eq!(record.variations.len(), 1);
+        assert_eq!(record.variations[0].at, 0);
+        assert_eq!(record.variations[0].variations.len(), 1);
+
+        let text = record.to_string();
+        let parsed = GameRecord::parse(&text).unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.to_string(), text);
+        let end = parsed.replay(&read_variants()).unwrap();
+        let mut board = start.clone();
+        main.iter().for_each(|mv| board.apply(*mv));
+        assert_eq!(end.key(), board.key());
+    }
+
+    #[test]
+    fn undone_line_is_a_variation() {
+        let start = klondike(42);
+        let first = play(&start, &[], &[0, 0, 0, 0]);
+        let second = play(&start, &[], &[1, 0, 0]);
+        assert_ne!(first[0], second[0]);
+        let mut tree = MoveTree::default();
+        // Play a line, undo back to the deal and play something else
+        for line in [&first[..], &first[..2], &[], &second[..]] {
+            tree.follow(line);
+        }
+        let record = GameRecord::from_tree("Klondike", Some(42), false, &start, &tree);
+        let written = GameRecord::new("Klondike", Some(42), false, &start, &second).unwrap();
+        assert_eq!(record.moves, written.moves);
+        let undone = GameRecord::new("Klondike", Some(42), false, &start, &first).unwrap();
+        assert_eq!(record.variations, vec![Variation {at: 0, moves: undone.moves, variations: Vec::new()}]);
+        assert!(GameRecord::parse(&record.to_string()).unwrap().replay(&read_variants()).is_ok());
+    }
+
+    #[test]
+    fn record_brackets_must_match() {
+        let tags = "[Variant \"Klondike\"]\n[Seed \"42\"]\n[Draw \"1\"]\n\n";
+        assert!(GameRecord::parse(&format!("{}d (t7-t5 d", tags)).unwrap_err().contains("isn't closed"));
+        assert!(GameRecord::parse(&format!("{}d d) d", tags)).unwrap_err().contains("wasn't opened"));
+        assert!(GameRecord::parse(&format!("{}(d) d", tags)).is_err());
+    }
+
+    #[test]
+    fn record_rejects_illegal_variation() {
+        let record = GameRecord::parse("[Variant \"Klondike\"]\n[Seed \"42\"]\n[Draw \"1\"]\n\nd (t1-t2) d").unwrap();
+        assert!(record.replay(&read_variants()).unwrap_err().starts_with("variation at move 1"));
+    }
+
+    #[test]
+    fn layout_round_trip() {
+        let board = klondike(7);
+        let text = format_layout("Klondike", &board);
+        let parsed = parse_layout(&variant("Klondike"), 1, None, &text).unwrap();
+        assert_eq!(format_layout("Klondike", &parsed), text);
+        assert_eq!(parsed.key(), board.key());
+        // The same again on one line
+        let parsed = parse_layout(&variant("Klondike"), 1, None, &text.replace('\n', "; ")).unwrap();
+        assert_eq!(format_layout("Klondike", &parsed), text);
+    }
+
+    #[test]
+    fn layout_rejections() {
+        let text = format_layout("Klondike", &klondike(7));
+        let parse = |text: &str| parse_layout(&variant("Klondike"), 1, None, text).unwrap_err();
+        assert_eq!(parse(&text.replace("t1 9c", "t1")), "9c is missing");
+        assert_eq!(parse(&text.replace("t1 9c", "t1 9s")), "9s appears 2 times, 9c is missing");
+        // Foundations start from an ace
+        let text = text.replace("t2 #2s Ks", "t2 Ks").replace("f1", "f1 2s");
+        assert_eq!(parse(&text), "2s can't go on f1");
+    }
+
+    #[test]
+    fn canfield_foundations_start_from_first_dealt() {
+        let canfield = variant("Canfield");
+        let board = Board::deal(&canfield, 3, None, false, shuffled_deck(1, 7));
+        let text = format_layout("Canfield", &board);
+        assert!(text.contains("f2 6c"));
+        let parsed = parse_layout(&canfield, 3, None, &text).unwrap();
+        assert!(parsed.foundations.iter().all(|foundation| foundation.rules.empty == EmptyRule::Rank(CardKind::Number(6))));
+        assert!(parsed.accepts(Pile::Foundation(0), card("6s"), 1));
+        assert!(!parsed.accepts(Pile::Foundation(0), card("As"), 1));
+
+        let without = text.replace("f2 6c", "f2").replace("t1 2c", "t1 2c 6c");
+        assert!(parse_layout(&canfield, 3, None, &without).unwrap_err().contains("needs a card on the completed piles"));
+    }
+}
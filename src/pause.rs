@@ -0,0 +1,313 @@
+//! Menu over the table while a game is paused with Escape
+
+use bevy::app::AppExit;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_easings::{EasingComponent, EasingState};
+
+use crate::game::{AutoPlay, DrawMode, DropHighlight, FontHandle, GameState, OpenDeal, RestartRequested, SmartClick, StockPasses, TakeBackPenalty, WinnableDeals};
+use crate::mouse_input::MouseInteraction;
+use crate::stats::Statistics;
+
+#[derive(Component)]
+pub struct PauseRoot;
+
+#[derive(Component)]
+pub enum PauseButton {
+    Resume,
+    Restart,
+    NewGame,
+    Settings,
+    Statistics,
+    Quit,
+    Back,
+}
+
+/// Options on the settings panel. Those about the deal take effect from the next one
+#[derive(Component)]
+pub enum SettingButton {
+    Draw,
+    Passes,
+    OpenDeal,
+    Winnable,
+    AutoPlay,
+    SmartClick,
+    TakeBackPenalty,
+    DropHighlight,
+}
+
+const SETTINGS: [SettingButton; 8] = [
+    SettingButton::Draw,
+    SettingButton::Passes,
+    SettingButton::OpenDeal,
+    SettingButton::Winnable,
+    SettingButton::AutoPlay,
+    SettingButton::SmartClick,
+    SettingButton::TakeBackPenalty,
+    SettingButton::DropHighlight,
+];
+
+/// Everything the settings panel can change
+#[derive(SystemParam)]
+pub struct Settings<'w> {
+    draw_mode: ResMut<'w, DrawMode>,
+    stock_passes: ResMut<'w, StockPasses>,
+    open_deal: ResMut<'w, OpenDeal>,
+    winnable: ResMut<'w, WinnableDeals>,
+    auto_play: ResMut<'w, AutoPlay>,
+    smart_click: ResMut<'w, SmartClick>,
+    penalty: ResMut<'w, TakeBackPenalty>,
+    drop_highlight: ResMut<'w, DropHighlight>,
+}
+
+impl Settings<'_> {
+    fn label(&self, button: &SettingButton) -> String {
+        let on_off = |on: bool| if on {"On"} else {"Off"};
+        match button {
+            SettingButton::Draw => format!("Draw: {}", if *self.draw_mode == DrawMode::Draw1 {"One"} else {"Three"}),
+            SettingButton::Passes => format!("Passes: {}", if *self.stock_passes == StockPasses::Unlimited {"Free"} else {"Vegas"}),
+            SettingButton::OpenDeal => format!("Thoughtful: {}", on_off(self.open_deal.0)),
+            SettingButton::Winnable => format!("Winnable Only: {}", on_off(self.winnable.0)),
+            SettingButton::AutoPlay => format!("Auto Play: {:?}", *self.auto_play),
+            SettingButton::SmartClick => format!("Smart Click: {}", on_off(self.smart_click.0)),
+            SettingButton::TakeBackPenalty => format!("Take Back Penalty: {}", on_off(self.penalty.0)),
+            SettingButton::DropHighlight => format!("Drop Highlight: {:?}", *self.drop_highlight),
+        }
+    }
+
+    /// Move on to the next value
+    fn change(&mut self, button: &SettingButton) {
+        match button {
+            SettingButton::Draw => {
+                *self.draw_mode = if *self.draw_mode == DrawMode::Draw1 {DrawMode::Draw3} else {DrawMode::Draw1};
+            },
+            SettingButton::Passes => {
+                *self.stock_passes = if *self.stock_passes == StockPasses::Unlimited {StockPasses::Vegas} else {StockPasses::Unlimited};
+            },
+            SettingButton::OpenDeal => self.open_deal.0 = !self.open_deal.0,
+            SettingButton::Winnable => self.winnable.0 = !self.winnable.0,
+            SettingButton::AutoPlay => {
+                *self.auto_play = match *self.auto_play {
+                    AutoPlay::Off => AutoPlay::Safe,
+                    AutoPlay::Safe => AutoPlay::Aggressive,
+                    AutoPlay::Aggressive => AutoPlay::Off,
+                };
+            },
+            SettingButton::SmartClick => self.smart_click.0 = !self.smart_click.0,
+            SettingButton::TakeBackPenalty => self.penalty.0 = !self.penalty.0,
+            SettingButton::DropHighlight => {
+                *self.drop_highlight = match *self.drop_highlight {
+                    DropHighlight::Off => DropHighlight::Tint,
+                    DropHighlight::Tint => DropHighlight::Outline,
+                    DropHighlight::Outline => DropHighlight::Off,
+                };
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Resource)]
+pub enum PausePanel {
+    #[default]
+    Main,
+    Settings,
+    Statistics,
+}
+
+/// Pause with Escape, unless a card is being dragged
+pub fn pause_system(
+    keys: Res<Input<KeyCode>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    q_interaction: Query<&MouseInteraction>,
+) {
+    if keys.just_pressed(KeyCode::Escape) && !q_interaction.iter().any(|interaction| interaction.is_dragging()) {
+        game_state.set(GameState::Paused);
+    }
+}
+
+pub fn freeze_animations(mut q_easing: Query<&mut EasingComponent<Transform>>) {
+    for mut easing in q_easing.iter_mut() {
+        easing.state = EasingState::Paused;
+    }
+}
+
+pub fn resume_animations(mut q_easing: Query<&mut EasingComponent<Transform>>) {
+    for mut easing in q_easing.iter_mut() {
+        easing.state = EasingState::Play;
+    }
+}
+
+pub fn spawn_button(parent: &mut ChildBuilder, font: &Handle<Font>, label: &str, button: impl Component) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(300.0),
+                height: Val::Px(55.0),
+                margin: UiRect::all(Val::Px(4.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+            ..Default::default()
+        })
+        .insert(button)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 32.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ));
+        });
+}
+
+pub fn spawn_text(parent: &mut ChildBuilder, font: &Handle<Font>, text: &str, size: f32) {
+    parent.spawn(TextBundle::from_section(
+            text,
+            TextStyle {
+                font: font.clone(),
+                font_size: size,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            margin: UiRect::all(Val::Px(4.0)),
+            ..Default::default()
+        }));
+}
+
+fn format_time(secs: u64) -> String {
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// Rebuild the overlay whenever a different panel is picked
+pub fn spawn_pause_menu(
+    mut commands: Commands,
+    font: Res<FontHandle>,
+    panel: Res<PausePanel>,
+    stats: Res<Statistics>,
+    settings: Settings,
+    q_root: Query<Entity, With<PauseRoot>>,
+) {
+    if !panel.is_changed() && !q_root.is_empty() {
+        return
+    }
+    for entity in q_root.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let font = &font.0;
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            z_index: ZIndex::Global(10),
+            ..Default::default()
+        })
+        .insert(PauseRoot)
+        .with_children(|parent| {
+            match *panel {
+                PausePanel::Main => {
+                    spawn_text(parent, font, "Paused", 60.0);
+                    spawn_button(parent, font, "Resume", PauseButton::Resume);
+                    spawn_button(parent, font, "Restart Same Deal", PauseButton::Restart);
+                    spawn_button(parent, font, "New Game", PauseButton::NewGame);
+                    spawn_button(parent, font, "Settings", PauseButton::Settings);
+                    spawn_button(parent, font, "Statistics", PauseButton::Statistics);
+                    spawn_button(parent, font, "Quit", PauseButton::Quit);
+                },
+                PausePanel::Settings => {
+                    spawn_text(parent, font, "Settings", 60.0);
+                    spawn_text(parent, font, "Deal options apply from the next deal", 24.0);
+                    for button in SETTINGS {
+                        spawn_button(parent, font, &settings.label(&button), button);
+                    }
+                    spawn_button(parent, font, "Back", PauseButton::Back);
+                },
+                PausePanel::Statistics => {
+                    spawn_text(parent, font, "Statistics", 60.0);
+                    if stats.0.is_empty() {
+                        spawn_text(parent, font, "No games played yet", 24.0);
+                    }
+                    for (name, record) in stats.0.iter() {
+                        let mut line = format!(
+                            "{}: won {} of {} ({:.0}%), stuck {}, streak {} (best {})",
+                            name, record.won, record.played, record.win_rate(), record.lost, record.streak, record.best_streak
+                        );
+                        if let (Some(time), Some(score), Some(moves)) = (record.best_time, record.best_score, record.fewest_moves) {
+                            line.push_str(&format!(", best time {}, best score {}, fewest moves {}", format_time(time), score, moves));
+                        }
+                        spawn_text(parent, font, &line, 24.0);
+                    }
+                    spawn_button(parent, font, "Back", PauseButton::Back);
+                },
+            }
+        });
+}
+
+pub fn despawn_pause_menu(mut commands: Commands, mut panel: ResMut<PausePanel>, q_root: Query<Entity, With<PauseRoot>>) {
+    for entity in q_root.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    *panel = PausePanel::Main;
+}
+
+pub fn pause_menu(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut panel: ResMut<PausePanel>,
+    mut settings: Settings,
+    mut ev_exit: EventWriter<AppExit>,
+    q_pause: Query<(&PauseButton, &Interaction), Changed<Interaction>>,
+    q_settings: Query<(&SettingButton, &Interaction, &Children), Changed<Interaction>>,
+    mut q_text: Query<&mut Text>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        if *panel == PausePanel::Main {
+            game_state.set(GameState::Playing);
+        } else {
+            *panel = PausePanel::Main;
+        }
+        return
+    }
+    for (button, interaction) in q_pause.iter() {
+        if *interaction != Interaction::Pressed {
+            continue
+        }
+        match button {
+            PauseButton::Resume => game_state.set(GameState::Playing),
+            PauseButton::Restart => {
+                commands.insert_resource(RestartRequested);
+                game_state.set(GameState::Shuffle);
+            },
+            PauseButton::NewGame => game_state.set(GameState::Menu),
+            PauseButton::Settings => *panel = PausePanel::Settings,
+            PauseButton::Statistics => *panel = PausePanel::Statistics,
+            PauseButton::Quit => ev_exit.send(AppExit),
+            PauseButton::Back => *panel = PausePanel::Main,
+        }
+    }
+    for (button, interaction, children) in q_settings.iter() {
+        if *interaction != Interaction::Pressed {
+            continue
+        }
+        settings.change(button);
+        let label = settings.label(button);
+        for child in children.iter() {
+            if let Ok(mut text) = q_text.get_mut(*child) {
+                text.sections[0].value = label.clone();
+            }
+        }
+    }
+}
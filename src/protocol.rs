@@ -0,0 +1,312 @@
+//! Line based protocol for playing without a window, so bots can be written in any language.
+//!
+//! Each command gets back any number of lines followed by `ok`, or a single `error <reason>` line.
+//!
+//! * `newgame <seed> [draw1|draw3] [vegas] [open] [variant <name>]` deals a new game
+//! * `load [draw1|draw3] [vegas] <layout>` starts a game from a layout written on one line with `;` between piles
+//! * `board` prints the position, one pile per line, with `##` for face down cards
+//! * `moves` prints every legal move on one line
+//! * `play <move>` makes a move, printing `won` if it wins the game
+//! * `undo` takes back the last move
+//! * `layout` prints the layout the game started from
+//! * `record` prints the game so far as a `GameRecord`, with the lines that were undone as variations
+//! * `quit` stops
+//!
+//! Cards and moves are written as described in `notation`
+
+use std::io::{BufRead, Write};
+
+use crate::board::{Board, Move, shuffled_deck};
+use crate::game::{Card, CardFace, DrawMode, StockPasses};
+use crate::notation::{GameRecord, card_code, format_layout, format_move, layout_variant, parse_layout, parse_move};
+use crate::tree::MoveTree;
+use crate::variant::{Variant, read_variants};
+
+/// Options shared by `newgame` and `load`
+struct GameOptions {
+    draw_mode: DrawMode,
+    stock_passes: StockPasses,
+    open: bool,
+    variant: String,
+}
+
+impl Default for GameOptions {
+    fn default() -> Self {
+        Self {
+            draw_mode: DrawMode::Draw1,
+            stock_passes: StockPasses::Unlimited,
+            open: false,
+            variant: "Klondike".to_string(),
+        }
+    }
+}
+
+impl GameOptions {
+    fn parse(&mut self, arg: &str) -> Result<(), String> {
+        match arg {
+            "draw1" => self.draw_mode = DrawMode::Draw1,
+            "draw3" => self.draw_mode = DrawMode::Draw3,
+            "vegas" => self.stock_passes = StockPasses::Vegas,
+            other => return Err(format!("unknown option {:?}", other)),
+        }
+        Ok(())
+    }
+
+    fn rules(&self, variant: &Variant) -> (usize, Option<usize>) {
+        (variant.draw_num(&self.draw_mode), variant.pass_limit(&self.draw_mode, &self.stock_passes))
+    }
+}
+
+struct Session {
+    variants: Vec<Variant>,
+    variant: String,
+    seed: Option<u64>,
+    open: bool,
+    board: Option<Board>,
+    /// Positions before each move, for undo
+    history: Vec<Board>,
+    moves: Vec<Move>,
+    /// Every line played, including those undone
+    tree: MoveTree,
+}
+
+impl Session {
+    fn board(&self) -> Result<&Board, String> {
+        self.board.as_ref().ok_or_else(|| "no game, start one with newgame".to_string())
+    }
+
+    fn new_game(&mut self, args: &[&str]) -> Result<Vec<String>, String> {
+        let seed = args.first()
+            .ok_or_else(|| "newgame needs a seed".to_string())?
+            .parse::<u64>()
+            .map_err(|e| format!("bad seed: {}", e))?;
+        let mut options = GameOptions::default();
+        let mut rest = args[1..].iter();
+        while let Some(arg) = rest.next() {
+            match *arg {
+                "open" => options.open = true,
+                // Names can have spaces in them so the variant takes the rest of the line
+                "variant" => {
+                    options.variant = rest.by_ref().copied().collect::<Vec<_>>().join(" ");
+                },
+                other => options.parse(other)?,
+            }
+        }
+        let variant = self.variant(&options.variant)?;
+        let (draw, passes) = options.rules(variant);
+        let board = Board::deal(variant, draw, passes, options.open, shuffled_deck(variant.decks, seed));
+        self.start(variant.name.clone(), Some(seed), options.open, board);
+        Ok(Vec::new())
+    }
+
+    /// Start from a layout, which takes the rest of the line after the options
+    fn load(&mut self, args: &[&str]) -> Result<Vec<String>, String> {
+        let mut options = GameOptions::default();
+        let mut rest = args;
+        while let [arg, others @ ..] = rest {
+            if options.parse(arg).is_err() {
+                break
+            }
+            rest = others;
+        }
+        let layout = rest.join(" ");
+        let variant = self.variant(&layout_variant(&layout).unwrap_or(options.variant.clone()))?;
+        let (draw, passes) = options.rules(variant);
+        let board = parse_layout(variant, draw, passes, &layout)?;
+        self.start(variant.name.clone(), None, false, board);
+        Ok(Vec::new())
+    }
+
+    fn variant(&self, name: &str) -> Result<&Variant, String> {
+        self.variants.iter()
+            .find(|variant| variant.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("no variant named {:?}", name))
+    }
+
+    fn start(&mut self, variant: String, seed: Option<u64>, open: bool, board: Board) {
+        self.board = Some(board);
+        self.variant = variant;
+        self.seed = seed;
+        self.open = open;
+        self.history.clear();
+        self.moves.clear();
+        self.tree = MoveTree::default();
+    }
+
+    /// Every pile on its own line, always in the same order so dumps can be compared
+    fn dump(&self) -> Result<Vec<String>, String> {
+        let board = self.board()?;
+        let faces = |cards: &[(Card, CardFace)]| cards.iter()
+            .map(|(card, face)| if *face == CardFace::Up {card_code(*card)} else {"##".to_string()})
+            .collect::<Vec<_>>();
+        let mut lines = vec![
+            format!("variant {}", self.variant),
+            format!("draw {}", board.draw),
+            format!("passes {}", board.passes.map(|passes| passes.to_string()).unwrap_or_else(|| "unlimited".to_string())),
+            format!("recycles {}", board.recycles),
+            format!("stock {}", board.stock.len()),
+            format!("waste {}", board.waste.iter().map(|card| card_code(*card)).collect::<Vec<_>>().join(" ")),
+        ];
+        if !board.reserve.is_empty() {
+            lines.push(format!("reserve {}", faces(&board.reserve).join(" ")));
+        }
+        for (i, foundation) in board.foundations.iter().enumerate() {
+            let cards: Vec<String> = foundation.cards.iter().map(|card| card_code(*card)).collect();
+            lines.push(format!("f{} {}", i + 1, cards.join(" ")));
+        }
+        for (i, tableau) in board.tableau.iter().enumerate() {
+            lines.push(format!("t{} {}", i + 1, faces(&tableau.cards).join(" ")));
+        }
+        // Trailing spaces from empty piles make dumps awkward to compare
+        Ok(lines.into_iter().map(|line| line.trim_end().to_string()).collect())
+    }
+
+    fn moves(&self) -> Result<Vec<String>, String> {
+        let board = self.board()?;
+        let moves: Vec<String> = board.moves().into_iter().map(|mv| format_move(board, mv)).collect();
+        Ok(vec![format!("moves {}", moves.join(" ")).trim_end().to_string()])
+    }
+
+    fn play(&mut self, text: &str) -> Result<Vec<String>, String> {
+        let board = self.board()?.clone();
+        let mv = parse_move(&board, text)?;
+        let mut next = board.clone();
+        next.apply(mv);
+        let won = next.is_won();
+        self.history.push(board);
+        self.moves.push(mv);
+        self.tree.follow(&self.moves);
+        self.board = Some(next);
+        Ok(if won {vec!["won".to_string()]} else {Vec::new()})
+    }
+
+    fn undo(&mut self) -> Result<Vec<String>, String> {
+        self.board()?;
+        self.board = Some(self.history.pop().ok_or_else(|| "nothing to undo".to_string())?);
+        self.moves.pop();
+        self.tree.follow(&self.moves);
+        Ok(Vec::new())
+    }
+
+    fn layout(&self) -> Result<Vec<String>, String> {
+        let start = self.history.first().unwrap_or(self.board()?);
+        Ok(format_layout(&self.variant, start).lines().map(|line| line.to_string()).collect())
+    }
+
+    fn record(&self) -> Result<Vec<String>, String> {
+        let start = self.history.first().unwrap_or(self.board()?);
+        let record = GameRecord::from_tree(&self.variant, self.seed, self.open, start, &self.tree);
+        Ok(record.to_string().lines().map(|line| line.to_string()).collect())
+    }
+}
+
+/// Answer commands until `quit` or the end of the input
+pub fn run(input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+    let mut session = Session {
+        variants: read_variants(),
+        variant: String::new(),
+        seed: None,
+        open: false,
+        board: None,
+        history: Vec::new(),
+        moves: Vec::new(),
+        tree: MoveTree::default(),
+    };
+    for line in input.lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let reply = match words.as_slice() {
+            [] => continue,
+            ["quit"] => break,
+            ["newgame", args @ ..] => session.new_game(args),
+            ["board"] => session.dump(),
+            ["moves"] => session.moves(),
+            ["play", mv] => session.play(mv),
+            ["undo"] => session.undo(),
+            ["load", args @ ..] => session.load(args),
+            ["layout"] => session.layout(),
+            ["record"] => session.record(),
+            [command, ..] => Err(format!("unknown command {:?}", command)),
+        };
+        match reply {
+            Ok(lines) => {
+                for line in lines {
+                    writeln!(output, "{}", line)?;
+                }
+                writeln!(output, "ok")?;
+            },
+            Err(e) => writeln!(output, "error {}", e)?,
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    fn session(commands: &str) -> String {
+        let mut output = Vec::new();
+        run(Cursor::new(commands), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn scripted_session() {
+        let commands = "\
+board
+newgame 42
+moves
+play t7-t5
+board
+play t1-f
+undo
+undo
+moves
+fly away
+quit
+board
+";
+        let expected = "\
+error no game, start one with newgame
+ok
+moves t7-t5 d
+ok
+ok
+variant Klondike
+draw 1
+passes unlimited
+recycles 0
+stock 24
+waste
+f1
+f2
+f3
+f4
+t1 2h
+t2 ## 5d
+t3 ## ## 8s
+t4 ## ## ## 8c
+t5 ## ## ## ## 7c 6d
+t6 ## ## ## ## ## 2d
+t7 ## ## ## ## ## Qd
+ok
+error 2h can't go on a foundation
+ok
+error nothing to undo
+moves t7-t5 d
+ok
+error unknown command \"fly\"
+";
+        assert_eq!(session(commands), expected);
+    }
+
+    #[test]
+    fn bad_arguments() {
+        assert_eq!(session("newgame\n"), "error newgame needs a seed\n");
+        assert_eq!(session("newgame 1 draw2\n"), "error unknown option \"draw2\"\n");
+        assert_eq!(session("newgame 1 variant Spider\nmoves\n"), "error no variant named \"Spider\"\nerror no game, start one with newgame\n");
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use crate::board::{Board, Move, Pile};
+use crate::game::CardFace;
+
+/// Deepest the search goes before backing out, draws included
+const MAX_DEPTH: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// Moves that win the game from the starting position
+    Solved(Vec<Move>),
+    /// Every legal line was searched without finding a win
+    Unsolvable,
+    /// Every line the search tries was searched without finding a win, but it skipped moves it judged
+    /// pointless, such as splitting runs, so a win through one of those hasn't been ruled out
+    Exhausted,
+    /// Ran out of time before the search finished
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub outcome: Outcome,
+    /// Distinct positions searched
+    pub nodes: usize,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    pub fn is_solved(&self) -> bool {
+        matches!(self.outcome, Outcome::Solved(_))
+    }
+}
+
+struct Search {
+    seen: HashSet<u64>,
+    path: Vec<Move>,
+    nodes: usize,
+    deadline: Instant,
+    out_of_time: bool,
+    /// Set when the search was cut short, so a failure doesn't prove anything
+    incomplete: bool,
+    /// Set when `priority` left out a legal move, so a failure only covers the moves tried
+    pruned: bool,
+}
+
+/// Depth first search for a win, giving up once `budget` has passed
+pub fn solve(board: &Board, budget: Duration) -> Report {
+    let start = Instant::now();
+    let mut search = Search {
+        seen: HashSet::new(),
+        path: Vec::new(),
+        nodes: 0,
+        deadline: start + budget,
+        out_of_time: false,
+        incomplete: false,
+        pruned: false,
+    };
+    let outcome = if search.dfs(board.clone()) {
+        Outcome::Solved(search.path)
+    } else if search.incomplete {
+        Outcome::Unknown
+    } else if search.pruned {
+        Outcome::Exhausted
+    } else {
+        Outcome::Unsolvable
+    };
+    Report {
+        outcome,
+        nodes: search.nodes,
+        elapsed: start.elapsed(),
+    }
+}
+
+impl Search {
+    fn dfs(&mut self, mut board: Board) -> bool {
+        let start = self.path.len();
+        while let Some(mv) = board.safe_foundation_move() {
+            board.apply(mv);
+            self.path.push(mv);
+        }
+        if board.is_won() {
+            return true
+        }
+        if !self.seen.insert(board.key()) {
+            self.path.truncate(start);
+            return false
+        }
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(1024) && Instant::now() > self.deadline {
+            self.out_of_time = true;
+        }
+        if self.out_of_time || self.path.len() > MAX_DEPTH {
+            self.incomplete = true;
+            self.path.truncate(start);
+            return false
+        }
+
+        let (moves, pruned) = candidates(&board);
+        self.pruned |= pruned;
+        for mv in moves {
+            if self.out_of_time {
+                break
+            }
+            let mut next = board.clone();
+            next.apply(mv);
+            self.path.push(mv);
+            if self.dfs(next) {
+                return true
+            }
+            self.path.pop();
+        }
+        self.path.truncate(start);
+        false
+    }
+}
+
+/// Legal moves worth searching, most promising first, and whether any legal move was left out
+fn candidates(board: &Board) -> (Vec<Move>, bool) {
+    let legal = board.moves();
+    let mut moves: Vec<(u8, Move)> = legal
+        .iter()
+        .filter_map(|mv| priority(board, *mv).map(|priority| (priority, *mv)))
+        .collect();
+    let pruned = moves.len() < legal.len();
+    moves.sort_by_key(|(priority, _)| *priority);
+    (moves.into_iter().map(|(_, mv)| mv).collect(), pruned)
+}
+
+/// Lower is tried first, `None` for moves that can't lead anywhere new
+fn priority(board: &Board, mv: Move) -> Option<u8> {
+    match mv {
+        Move::Cards {to: Pile::Foundation(_), ..} => Some(0),
+        Move::Cards {from: Pile::Tableau(from), to: Pile::Tableau(to), count} => {
+            let cards = &board.tableau[from].cards;
+            match cards.len().checked_sub(count + 1).map(|i| cards[i]) {
+                // Turns over a card
+                Some((_, CardFace::Down)) => Some(1),
+                // Moving a whole pile into an empty space just swaps them around
+                None if board.tableau[to].cards.is_empty() => None,
+                None => Some(2),
+                Some((card, CardFace::Up)) => {
+                    if count == board.run_len(from) {
+                        // Only happens in an open deal, where the card underneath isn't part of the run
+                        Some(3)
+                    } else if (0..board.foundations.len()).any(|i| board.accepts(Pile::Foundation(i), card, 1)) {
+                        // Splitting a run is only worth it to free the card underneath
+                        Some(2)
+                    } else {
+                        None
+                    }
+                },
+            }
+        },
+        Move::Cards {from: Pile::Foundation(_), ..} => None,
+        Move::Cards {..} => Some(2),
+        Move::Draw => Some(4),
+        Move::Recycle => Some(5),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::fixtures::{built_up, card, empty_board};
+
+    #[test]
+    fn solves_a_king_blocking_its_queen() {
+        let mut board = built_up(12);
+        let hearts = board.foundations.iter_mut().find(|foundation| foundation.suit == card("Kh").suit).unwrap();
+        hearts.cards.truncate(11);
+        board.tableau[0].cards = vec![(card("Qh"), CardFace::Down), (card("Kh"), CardFace::Up)];
+        let report = solve(&board, Duration::from_secs(10));
+        let Outcome::Solved(moves) = report.outcome else {
+            panic!("{:?}", report.outcome)
+        };
+        // The king makes way, then both go home
+        assert_eq!(moves.len(), 3);
+        assert!(matches!(moves[0], Move::Cards {from: Pile::Tableau(0), to: Pile::Tableau(_), count: 1}));
+        for mv in moves {
+            assert!(board.moves().contains(&mv));
+            board.apply(mv);
+        }
+        assert!(board.is_won());
+    }
+
+    #[test]
+    fn no_moves_is_unsolvable() {
+        let mut board = empty_board();
+        board.tableau[0].cards = vec![(card("5d"), CardFace::Down), (card("5s"), CardFace::Up)];
+        board.tableau[1].cards = vec![(card("6c"), CardFace::Up)];
+        assert_eq!(solve(&board, Duration::from_secs(10)).outcome, Outcome::Unsolvable);
+        // Drawing around and around the stock never gets the card underneath out
+        board.stock = vec![card("9h"), card("8c")];
+        assert_eq!(solve(&board, Duration::from_secs(10)).outcome, Outcome::Unsolvable);
+    }
+}
@@ -0,0 +1,115 @@
+//! Games played and won, kept on disk for each variant and draw
+
+use std::collections::BTreeMap;
+use std::fs;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Deal, GameClock, GameStats};
+use crate::storage::data_dir;
+use crate::variant::Variant;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Record {
+    pub played: usize,
+    pub won: usize,
+    /// Games given up once there were no moves left
+    #[serde(default)]
+    pub lost: usize,
+    /// Fastest win in seconds
+    pub best_time: Option<u64>,
+    pub best_score: Option<i32>,
+    pub fewest_moves: Option<usize>,
+    pub streak: usize,
+    pub best_streak: usize,
+    /// The last game started hasn't been won, so starting another one ends the streak
+    pub unfinished: bool,
+}
+
+impl Record {
+    pub fn win_rate(&self) -> f32 {
+        if self.played == 0 {0.0} else {100.0 * self.won as f32 / self.played as f32}
+    }
+}
+
+/// Records by variant and number of cards drawn, such as `Klondike draw 3`
+#[derive(Debug, Default, Resource)]
+pub struct Statistics(pub BTreeMap<String, Record>);
+
+pub fn key(variant: &str, draw: usize) -> String {
+    format!("{} draw {}", variant, draw)
+}
+
+impl Statistics {
+    fn path() -> std::path::PathBuf {
+        data_dir().join("stats.ron")
+    }
+
+    pub fn load() -> Self {
+        let records = fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|text| ron::from_str(&text).map_err(|e| error!("Failed to read the statistics: {}", e)).ok())
+            .unwrap_or_default();
+        Self(records)
+    }
+
+    pub fn save(&self) {
+        let path = Self::path();
+        let text = ron::ser::to_string_pretty(&self.0, Default::default()).unwrap();
+        if let Err(e) = fs::create_dir_all(data_dir()).and_then(|_| fs::write(&path, text)) {
+            error!("Failed to save the statistics to {}: {}", path.display(), e);
+        }
+    }
+}
+
+impl Statistics {
+    /// Give up on the game being played because there are no moves left
+    pub fn lose(&mut self, variant: &str, draw: usize) {
+        let record = self.0.entry(key(variant, draw)).or_default();
+        if !record.unfinished {
+            return
+        }
+        record.lost += 1;
+        record.unfinished = false;
+        record.streak = 0;
+        self.save();
+    }
+}
+
+/// Count every deal laid out as a game played, including restarts of the same deal
+pub fn stats_started_system(deal: Option<Res<Deal>>, variant: Res<Variant>, mut stats: ResMut<Statistics>) {
+    let deal = if let Some(d) = deal {d} else {return};
+    if !deal.is_changed() {
+        return
+    }
+    let record = stats.0.entry(key(&variant.name, deal.board.draw)).or_default();
+    if record.unfinished {
+        record.streak = 0;
+    }
+    record.played += 1;
+    record.unfinished = true;
+    stats.save();
+}
+
+pub fn stats_won_system(
+    deal: Option<Res<Deal>>,
+    variant: Res<Variant>,
+    clock: Res<GameClock>,
+    game: Res<GameStats>,
+    mut stats: ResMut<Statistics>,
+) {
+    let deal = if let Some(d) = deal {d} else {return};
+    let record = stats.0.entry(key(&variant.name, deal.board.draw)).or_default();
+    if !record.unfinished {
+        return
+    }
+    let secs = clock.0.elapsed().as_secs();
+    record.won += 1;
+    record.unfinished = false;
+    record.streak += 1;
+    record.best_streak = record.best_streak.max(record.streak);
+    record.best_time = Some(record.best_time.map_or(secs, |best| best.min(secs)));
+    record.best_score = Some(record.best_score.map_or(game.score, |best| best.max(game.score)));
+    record.fewest_moves = Some(record.fewest_moves.map_or(game.moves, |best| best.min(game.moves)));
+    stats.save();
+}
@@ -0,0 +1,16 @@
+//! Where files are kept between runs
+
+use std::env;
+use std::path::PathBuf;
+
+/// `SOLITAIRE_DATA_DIR` if it is set, otherwise a `solitaire` folder in the platform's usual place for app data
+pub fn data_dir() -> PathBuf {
+    if let Ok(dir) = env::var("SOLITAIRE_DATA_DIR") {
+        return PathBuf::from(dir)
+    }
+    env::var("XDG_DATA_HOME").map(PathBuf::from)
+        .or_else(|_| env::var("APPDATA").map(PathBuf::from))
+        .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+        .unwrap_or_else(|_| PathBuf::from("."))
+        .join("solitaire")
+}
@@ -0,0 +1,134 @@
+//! Every line of play tried in a game, so undoing a move and playing something else doesn't lose the first line
+
+use crate::board::Move;
+
+#[derive(Debug, Clone)]
+struct Node {
+    /// `None` for the deal itself
+    mv: Option<Move>,
+    parent: usize,
+    /// In the order they were first played
+    children: Vec<usize>,
+    /// The child last gone down, which is where redoing from here leads
+    next: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MoveTree {
+    nodes: Vec<Node>,
+    current: usize,
+}
+
+impl Default for MoveTree {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node {mv: None, parent: 0, children: Vec::new(), next: None}],
+            current: Self::ROOT,
+        }
+    }
+}
+
+impl MoveTree {
+    /// The deal, before any moves
+    pub const ROOT: usize = 0;
+
+    /// The position on the table
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn mv(&self, node: usize) -> Option<Move> {
+        self.nodes[node].mv
+    }
+
+    pub fn parent(&self, node: usize) -> Option<usize> {
+        (node != Self::ROOT).then(|| self.nodes[node].parent)
+    }
+
+    pub fn children(&self, node: usize) -> &[usize] {
+        &self.nodes[node].children
+    }
+
+    pub fn next(&self, node: usize) -> Option<usize> {
+        self.nodes[node].next
+    }
+
+    /// Moves from the deal to a node
+    pub fn line(&self, mut node: usize) -> Vec<Move> {
+        let mut moves = Vec::new();
+        while let Some(mv) = self.nodes[node].mv {
+            moves.push(mv);
+            node = self.nodes[node].parent;
+        }
+        moves.reverse();
+        moves
+    }
+
+    /// Nodes from a node onwards, always going down the child last gone down
+    pub fn continuation(&self, mut node: usize) -> Vec<usize> {
+        let mut nodes = vec![node];
+        while let Some(next) = self.nodes[node].next {
+            nodes.push(next);
+            node = next;
+        }
+        nodes
+    }
+
+    /// Move to the position after these moves from the deal, adding any that haven't been played from there before
+    pub fn follow(&mut self, moves: &[Move]) {
+        let mut node = Self::ROOT;
+        for mv in moves {
+            let child = match self.nodes[node].children.iter().find(|child| self.nodes[**child].mv == Some(*mv)) {
+                Some(child) => *child,
+                None => {
+                    self.nodes.push(Node {mv: Some(*mv), parent: node, children: Vec::new(), next: None});
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children.push(child);
+                    child
+                },
+            };
+            self.nodes[node].next = Some(child);
+            node = child;
+        }
+        self.current = node;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Pile;
+
+    #[test]
+    fn undone_lines_are_kept() {
+        let a = Move::Draw;
+        let b = Move::Cards {from: Pile::Waste, to: Pile::Tableau(0), count: 1};
+        let c = Move::Recycle;
+        let d = Move::Cards {from: Pile::Tableau(1), to: Pile::Foundation(0), count: 1};
+        let mut tree = MoveTree::default();
+        tree.follow(&[a, b, c]);
+        let (node_a, node_b, node_c) = (1, 2, 3);
+        assert_eq!(tree.current(), node_c);
+        assert_eq!(tree.line(node_c), vec![a, b, c]);
+
+        // Undoing back to after the first move still leads on down the undone moves
+        tree.follow(&[a]);
+        assert_eq!(tree.current(), node_a);
+        assert_eq!(tree.continuation(node_a), vec![node_a, node_b, node_c]);
+
+        tree.follow(&[a, d]);
+        let node_d = tree.current();
+        assert_eq!(tree.children(node_a), &[node_b, node_d]);
+        assert_eq!(tree.next(node_a), Some(node_d));
+        assert_eq!(tree.parent(node_d), Some(node_a));
+        assert_eq!(tree.continuation(MoveTree::ROOT), vec![MoveTree::ROOT, node_a, node_d]);
+
+        // Going back down the first line finds its nodes again
+        tree.follow(&[a, b, c]);
+        assert_eq!(tree.current(), node_c);
+        assert_eq!(tree.children(node_a), &[node_b, node_d]);
+        assert_eq!(tree.next(node_a), Some(node_b));
+        assert_eq!(tree.children(MoveTree::ROOT), &[node_a]);
+        assert_eq!(tree.parent(MoveTree::ROOT), None);
+    }
+}
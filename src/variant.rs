@@ -0,0 +1,405 @@
+use std::fs;
+use std::path::PathBuf;
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use serde::Deserialize;
+
+use crate::game::{Card, CardFace, CardKind, DrawMode, StockPasses, CARD_WIDTH, WINDOW_WIDTH};
+
+/// Which suits can be built on each other in a stack
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum SuitRule {
+    AlternateColour,
+    SameSuit,
+    Any,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum Direction {
+    Descending,
+    Ascending,
+}
+
+/// What can be placed on a pile while it is empty
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum EmptyRule {
+    Any,
+    Rank(CardKind),
+    /// The pile stays empty once it has been cleared
+    Nothing,
+    /// The rank of the first card dealt to the completed piles. Replaced with `Rank` when the cards are dealt
+    FirstDealt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct BuildRules {
+    pub suit: SuitRule,
+    pub direction: Direction,
+    /// Ranks wrap around between King and Ace
+    #[serde(default)]
+    pub wrap: bool,
+    pub empty: EmptyRule,
+}
+
+impl BuildRules {
+    /// Return true if `card` can be placed on `stack_card`, or start the pile if it is empty
+    pub fn accepts(&self, stack_card: Option<&Card>, card: Card) -> bool {
+        let stack_card = match stack_card {
+            Some(stack_card) => stack_card,
+            None => return match self.empty {
+                EmptyRule::Any => true,
+                EmptyRule::Rank(rank) => rank == card.kind,
+                EmptyRule::Nothing | EmptyRule::FirstDealt => false,
+            },
+        };
+        let suit_matches = match self.suit {
+            SuitRule::AlternateColour => card.suit.can_stack(&stack_card.suit),
+            SuitRule::SameSuit => card.suit == stack_card.suit,
+            SuitRule::Any => true,
+        };
+        let rank_matches = match (self.direction, self.wrap) {
+            (Direction::Descending, false) => card.kind.can_stack(&stack_card.kind),
+            (Direction::Descending, true) => card.kind.next_wrapping() == stack_card.kind,
+            (Direction::Ascending, false) => stack_card.kind.next() == Some(card.kind),
+            (Direction::Ascending, true) => stack_card.kind.next_wrapping() == card.kind,
+        };
+        suit_matches && rank_matches
+    }
+
+    /// Return true if each card, from the bottom up, can be built on the one before it
+    pub fn is_run(&self, cards: &[Card]) -> bool {
+        cards.windows(2).all(|pair| self.accepts(Some(&pair[0]), pair[1]))
+    }
+
+    /// The rank on top of a pile once it holds a full suit
+    pub fn last_rank(&self) -> CardKind {
+        match (self.empty, self.wrap, self.direction) {
+            (EmptyRule::Rank(base), true, Direction::Ascending) => base.prev_wrapping(),
+            (EmptyRule::Rank(base), true, Direction::Descending) => base.next_wrapping(),
+            (_, _, Direction::Ascending) => CardKind::King,
+            (_, _, Direction::Descending) => CardKind::Ace,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum PileKind {
+    Stock,
+    Waste,
+    Reserve,
+    Foundation,
+    Tableau,
+}
+
+/// Where a pile sits on the table
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum Slot {
+    /// Slot in the top row, next to the deck
+    Top(usize),
+    /// Slot in the tableau row
+    Row(usize),
+}
+
+/// A group of identical piles laid out in neighbouring slots
+#[derive(Debug, Clone, Deserialize)]
+pub struct PileDef {
+    pub kind: PileKind,
+    /// Slot of the first pile in the group
+    pub at: Slot,
+    #[serde(default = "one")]
+    pub count: usize,
+    /// Cards dealt to each pile from the bottom up, `D` for face down and `U` for face up.
+    /// The last pattern is repeated for any remaining piles
+    #[serde(default)]
+    pub deal: Vec<String>,
+    /// Required for foundations and tableau piles
+    #[serde(default)]
+    pub build: Option<BuildRules>,
+}
+
+impl PileDef {
+    /// The face up/down pattern dealt to the `i`th pile of the group
+    pub fn pattern(&self, i: usize) -> Vec<CardFace> {
+        self.deal
+            .get(i.min(self.deal.len().saturating_sub(1)))
+            .map(|pattern| pattern.chars().map(|c| if c == 'D' {CardFace::Down} else {CardFace::Up}).collect())
+            .unwrap_or_default()
+    }
+
+    /// Number of slots taken up by the group
+    pub fn last_slot(&self) -> usize {
+        match self.at {
+            Slot::Top(slot) | Slot::Row(slot) => slot + self.count - 1,
+        }
+    }
+}
+
+/// Rules and layout of a solitaire game, loaded from `assets/variants`
+#[derive(Debug, Clone, Deserialize, Resource)]
+pub struct Variant {
+    pub name: String,
+    /// Number of full decks shuffled together
+    #[serde(default = "one")]
+    pub decks: usize,
+    /// Cards flipped from the deck each draw, `None` uses the draw mode picked in the menu
+    #[serde(default)]
+    pub draw: Option<usize>,
+    /// Passes through the deck, `None` if there is no limit
+    #[serde(default)]
+    pub passes: Option<usize>,
+    /// Only the top card of a stack may be moved
+    #[serde(default)]
+    pub single_card_moves: bool,
+    /// Empty tableau piles are refilled from the reserve
+    #[serde(default)]
+    pub auto_fill: bool,
+    /// The top card of a completed pile can be taken back down onto the tableau
+    #[serde(default)]
+    pub foundation_moves: bool,
+    /// Number of cards below the top of the discard pile that are fanned out
+    #[serde(default = "default_discard_fan")]
+    pub discard_fan: usize,
+    /// Horizontal distance between the centers of neighbouring piles
+    #[serde(default = "default_pile_spacing")]
+    pub pile_spacing: f32,
+    /// Piles are dealt to in the order they are listed, the stock gets whatever is left over
+    pub piles: Vec<PileDef>,
+}
+
+fn one() -> usize {
+    1
+}
+
+fn default_discard_fan() -> usize {
+    2
+}
+
+fn default_pile_spacing() -> f32 {
+    175.0
+}
+
+impl Variant {
+    /// Number of cards flipped from the deck on each draw
+    pub fn draw_num(&self, draw_mode: &DrawMode) -> usize {
+        self.draw.unwrap_or_else(|| draw_mode.num())
+    }
+
+    /// Passes through the deck allowed in this game, `None` if there is no limit
+    pub fn pass_limit(&self, draw_mode: &DrawMode, stock_passes: &StockPasses) -> Option<usize> {
+        self.passes.or(match stock_passes {
+            StockPasses::Unlimited => None,
+            StockPasses::Vegas => Some(if self.draw_num(draw_mode) >= 3 {3} else {1}),
+        })
+    }
+
+    /// Window width needed to fit every pile
+    pub fn table_width(&self) -> f32 {
+        let last_slot = self.piles.iter().map(|pile| pile.last_slot()).max().unwrap_or(0);
+        // 50 pixels of margin on the left and 40 on the right
+        (90.0 + (self.pile_spacing * last_slot as f32) + CARD_WIDTH).max(WINDOW_WIDTH)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let count = |kind| self.piles.iter().filter(|pile| pile.kind == kind).map(|pile| pile.count).sum::<usize>();
+        if count(PileKind::Stock) != 1 || count(PileKind::Waste) != 1 {
+            return Err("there must be exactly one stock and one waste pile".to_string())
+        }
+        if count(PileKind::Reserve) > 1 {
+            return Err("there can be at most one reserve".to_string())
+        }
+        let mut dealt = 0;
+        for pile in self.piles.iter() {
+            if pile.count == 0 {
+                return Err(format!("{:?} pile group is empty", pile.kind))
+            }
+            match pile.kind {
+                PileKind::Foundation | PileKind::Tableau if pile.build.is_none() => {
+                    return Err(format!("{:?} piles need build rules", pile.kind))
+                },
+                PileKind::Foundation if pile.count % 4 != 0 => {
+                    return Err("foundations must be split evenly between the suits".to_string())
+                },
+                _ => {},
+            }
+            if pile.build.map(|build| build.empty == EmptyRule::FirstDealt).unwrap_or(false) {
+                if pile.kind != PileKind::Foundation {
+                    return Err("only foundations can start from the first dealt card".to_string())
+                }
+                dealt += 1;
+            }
+            if let Some(pattern) = pile.deal.iter().find(|pattern| pattern.chars().any(|c| c != 'D' && c != 'U')) {
+                return Err(format!("deal pattern {:?} can only contain D and U", pattern))
+            }
+            dealt += (0..pile.count).map(|i| pile.pattern(i).len()).sum::<usize>();
+        }
+        if dealt > self.decks * 52 {
+            return Err(format!("{} cards are dealt but there are only {} in the deck", dealt, self.decks * 52))
+        }
+        Ok(())
+    }
+}
+
+/// The current variant along with the options picked in the menu
+#[derive(SystemParam)]
+pub struct GameRules<'w> {
+    pub variant: Res<'w, Variant>,
+    pub draw_mode: Res<'w, DrawMode>,
+    pub stock_passes: Res<'w, StockPasses>,
+}
+
+impl GameRules<'_> {
+    pub fn draw_num(&self) -> usize {
+        self.variant.draw_num(&self.draw_mode)
+    }
+
+    pub fn pass_limit(&self) -> Option<usize> {
+        self.variant.pass_limit(&self.draw_mode, &self.stock_passes)
+    }
+}
+
+/// Every variant that was successfully loaded
+#[derive(Debug, Resource)]
+pub struct Variants(pub Vec<Variant>);
+
+/// Mirrors where bevy looks for the assets folder
+fn asset_dir() -> PathBuf {
+    if let Ok(root) = std::env::var("BEVY_ASSET_ROOT") {
+        PathBuf::from(root).join("assets")
+    } else if let Ok(manifest_dir) = std::env::var("CARGO_MANIFEST_DIR") {
+        PathBuf::from(manifest_dir).join("assets")
+    } else {
+        std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()))
+            .unwrap_or_default()
+            .join("assets")
+    }
+}
+
+/// Read a variant definition and check it can be dealt
+fn parse_variant(text: &str) -> Result<Variant, String> {
+    let variant = ron::from_str::<Variant>(text).map_err(|e| e.to_string())?;
+    variant.validate()?;
+    Ok(variant)
+}
+
+pub fn read_variants() -> Vec<Variant> {
+    let dir = asset_dir().join("variants");
+    let mut paths = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map(|ext| ext == "ron").unwrap_or(false))
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            error!("Failed to read {}: {}", dir.display(), e);
+            Vec::new()
+        },
+    };
+    paths.sort();
+
+    let mut variants = Vec::new();
+    for path in paths {
+        let parsed = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| parse_variant(&text));
+        match parsed {
+            Ok(variant) => variants.push(variant),
+            Err(e) => error!("Invalid variant {}: {}", path.display(), e),
+        }
+    }
+    variants
+}
+
+pub fn load_variants(mut commands: Commands) {
+    let variants = read_variants();
+    let default = variants
+        .iter()
+        .find(|variant| variant.name == "Klondike")
+        .or(variants.first())
+        .cloned()
+        .expect("no solitaire variants could be loaded");
+    commands.insert_resource(default);
+    commands.insert_resource(Variants(variants));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::fixtures::{card, variant};
+
+    #[test]
+    fn wrapping_builds() {
+        let mut foundation = BuildRules {
+            suit: SuitRule::SameSuit,
+            direction: Direction::Ascending,
+            wrap: true,
+            empty: EmptyRule::Rank(CardKind::Number(6)),
+        };
+        assert!(foundation.accepts(None, card("6s")));
+        assert!(!foundation.accepts(None, card("As")));
+        assert!(foundation.accepts(Some(&card("Ks")), card("As")));
+        assert!(!foundation.accepts(Some(&card("Ks")), card("Ah")));
+        assert!(foundation.accepts(Some(&card("As")), card("2s")));
+        assert_eq!(foundation.last_rank(), CardKind::Number(5));
+        foundation.wrap = false;
+        assert!(!foundation.accepts(Some(&card("Ks")), card("As")));
+        assert_eq!(foundation.last_rank(), CardKind::King);
+
+        let mut tableau = BuildRules {
+            suit: SuitRule::AlternateColour,
+            direction: Direction::Descending,
+            wrap: true,
+            empty: EmptyRule::Any,
+        };
+        assert!(tableau.accepts(Some(&card("Ah")), card("Ks")));
+        assert!(!tableau.accepts(Some(&card("Ah")), card("Kd")));
+        assert!(tableau.accepts(Some(&card("2h")), card("As")));
+        tableau.empty = EmptyRule::Rank(CardKind::Number(6));
+        assert_eq!(tableau.last_rank(), CardKind::Number(7));
+        tableau.wrap = false;
+        assert!(!tableau.accepts(Some(&card("Ah")), card("Ks")));
+        assert_eq!(tableau.last_rank(), CardKind::Ace);
+    }
+
+    #[test]
+    fn vegas_pass_limits() {
+        let klondike = variant("Klondike");
+        assert_eq!(klondike.pass_limit(&DrawMode::Draw1, &StockPasses::Unlimited), None);
+        assert_eq!(klondike.pass_limit(&DrawMode::Draw1, &StockPasses::Vegas), Some(1));
+        assert_eq!(klondike.pass_limit(&DrawMode::Draw3, &StockPasses::Vegas), Some(3));
+        // Canfield always draws three
+        assert_eq!(variant("Canfield").pass_limit(&DrawMode::Draw1, &StockPasses::Vegas), Some(3));
+        // and Forty Thieves has a single pass whatever the setting
+        assert_eq!(variant("Forty Thieves").pass_limit(&DrawMode::Draw3, &StockPasses::Unlimited), Some(1));
+    }
+
+    #[test]
+    fn shipped_variants() {
+        let dir = asset_dir().join("variants");
+        let mut names = Vec::new();
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let variant = parse_variant(&fs::read_to_string(&path).unwrap())
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            names.push(variant.name);
+        }
+        names.sort();
+        assert_eq!(names, ["Canfield", "Forty Thieves", "Klondike"]);
+        assert_eq!(read_variants().len(), 3);
+    }
+
+    #[test]
+    fn bad_variants() {
+        let klondike = fs::read_to_string(asset_dir().join("variants").join("klondike.ron")).unwrap();
+        let bad = |from: &str, to: &str| {
+            assert!(klondike.contains(from));
+            parse_variant(&klondike.replace(from, to)).unwrap_err()
+        };
+        assert_eq!(bad("count: 4", "count: 3"), "foundations must be split evenly between the suits");
+        assert_eq!(bad("count: 7", "count: 0"), "Tableau pile group is empty");
+        assert_eq!(bad("(kind: Waste, at: Top(1)),", ""), "there must be exactly one stock and one waste pile");
+        assert_eq!(bad("\"DDDDDDU\"", &format!("\"{}U\"", "D".repeat(49))), "71 cards are dealt but there are only 52 in the deck");
+        assert!(bad("AlternateColour", "Rainbow").contains("Rainbow"));
+        assert_eq!(bad("DDDU\"", "DDXU\""), "deal pattern \"DDXU\" can only contain D and U");
+    }
+}